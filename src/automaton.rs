@@ -0,0 +1,335 @@
+use std::iter::repeat_n;
+
+/// Something that evolves one generation at a time.
+pub trait Automaton {
+    fn step(&mut self);
+    fn generation(&self) -> usize;
+
+    /// Every generation from the current one on, each cloned before it is stepped.
+    fn generations(self) -> Generations<Self> where Self: Sized + Clone {
+        Generations(self)
+    }
+}
+
+#[derive(Clone)]
+pub struct Generations<A>(A);
+
+impl<A: Automaton + Clone> Iterator for Generations<A> {
+    type Item = A;
+
+    fn next(&mut self) -> Option<A> {
+        let result = self.0.clone();
+        self.0.step();
+        Some(result)
+    }
+}
+
+/// Next state of a cell on a line, from the window of `2 * radius + 1` cells centred on it.
+pub trait LineRule<S> {
+    fn next(&self, window: &[S]) -> S;
+}
+
+impl<S, F: Fn(&[S]) -> S> LineRule<S> for F {
+    fn next(&self, window: &[S]) -> S {
+        self(window)
+    }
+}
+
+/// A rule for two-state lines given as a lookup table.  Windows are read as binary numbers with
+/// the leftmost cell as the most significant bit; any window not listed dies.
+#[derive(Clone, Debug)]
+pub struct RuleTable {
+    alive: Vec<bool>
+}
+
+impl RuleTable {
+    pub fn new(radius: usize, rules: impl IntoIterator<Item=(usize, bool)>) -> RuleTable {
+        let mut alive = vec![false; 1 << (2 * radius + 1)];
+        for (window, outcome) in rules {
+            alive[window] = outcome;
+        }
+        RuleTable { alive }
+    }
+}
+
+impl LineRule<bool> for RuleTable {
+    fn next(&self, window: &[bool]) -> bool {
+        self.alive[window.iter().fold(0, |acc, &cell| 2 * acc + usize::from(cell))]
+    }
+}
+
+/// An unbounded line of cells in which all but finitely many hold the `background` state.  Only
+/// the stretch from the first to the last other cell is stored, and it grows or shrinks as the
+/// line evolves.
+#[derive(Clone, Debug)]
+pub struct Line<S, R> {
+    cells: Vec<S>,
+    next: Vec<S>,
+    padded: Vec<S>,
+    /// Position of `cells[0]`.
+    offset: isize,
+    background: S,
+    radius: usize,
+    rule: R,
+    generation: usize
+}
+
+impl<S: Copy + Eq, R: LineRule<S>> Line<S, R> {
+    /// The rule must map a window of background cells to the background, or the line would
+    /// fill up everywhere at once.
+    pub fn new(cells: Vec<S>, offset: isize, background: S, radius: usize, rule: R) -> Line<S, R> {
+        assert!(
+            rule.next(&vec![background; 2 * radius + 1]) == background,
+            "the rule must leave the background alone"
+        );
+
+        let mut line = Line {
+            cells,
+            next: Vec::new(),
+            padded: Vec::new(),
+            offset,
+            background,
+            radius,
+            rule,
+            generation: 0
+        };
+        line.trim();
+        line
+    }
+
+    pub fn get(&self, position: isize) -> S {
+        usize::try_from(position - self.offset).ok()
+            .and_then(|i| self.cells.get(i).copied())
+            .unwrap_or(self.background)
+    }
+
+    /// Positions and states of the stored stretch of the line.
+    pub fn cells(&self) -> impl Iterator<Item=(isize, S)> + '_ {
+        self.cells.iter().enumerate().map(|(i, &cell)| (self.offset + i as isize, cell))
+    }
+
+    fn trim(&mut self) {
+        let background = self.background;
+        let leading = self.cells.iter().take_while(|&&cell| cell == background).count();
+        self.cells.drain(..leading);
+        self.offset += leading as isize;
+
+        let trailing = self.cells.iter().rev().take_while(|&&cell| cell == background).count();
+        self.cells.truncate(self.cells.len() - trailing);
+    }
+}
+
+impl<S: Copy + Eq, R: LineRule<S>> Automaton for Line<S, R> {
+    fn step(&mut self) {
+        let reach = 2 * self.radius;
+        self.padded.clear();
+        self.padded.extend(repeat_n(self.background, reach));
+        self.padded.extend(&self.cells);
+        self.padded.extend(repeat_n(self.background, reach));
+
+        self.next.clear();
+        self.next.extend(self.padded.windows(reach + 1).map(|window| self.rule.next(window)));
+
+        std::mem::swap(&mut self.cells, &mut self.next);
+        self.offset -= self.radius as isize;
+        self.generation += 1;
+        self.trim();
+    }
+
+    fn generation(&self) -> usize {
+        self.generation
+    }
+}
+
+/// Which cells around a grid cell count as its neighbours, out to a radius.
+#[derive(Copy, Clone, Debug)]
+pub enum Neighbourhood {
+    /// Every cell within the square.
+    Moore(usize),
+    /// Every cell within the diamond.
+    VonNeumann(usize)
+}
+
+impl Neighbourhood {
+    /// `(row, col)` offsets of the neighbours, not including the cell itself.
+    pub fn offsets(self) -> Vec<(isize, isize)> {
+        let (radius, within): (usize, fn(isize, isize, isize) -> bool) = match self {
+            Neighbourhood::Moore(radius) => (radius, |_, _, _| true),
+            Neighbourhood::VonNeumann(radius) => (radius, |r, dr, dc| dr.abs() + dc.abs() <= r)
+        };
+        let r = radius as isize;
+
+        (-r..=r)
+            .flat_map(|dr| (-r..=r).map(move |dc| (dr, dc)))
+            .filter(|&(dr, dc)| (dr, dc) != (0, 0) && within(r, dr, dc))
+            .collect()
+    }
+}
+
+/// What lies beyond the edges of a grid.
+#[derive(Copy, Clone, Debug)]
+pub enum Edge<S> {
+    /// Nothing: cells near the edge just have fewer neighbours.
+    Omit,
+    /// The grid wraps around like a torus.
+    Wrap,
+    /// Cells in this state.
+    Constant(S)
+}
+
+/// Next state of a grid cell from its own state and its neighbours' states.
+pub trait GridRule<S> {
+    fn next(&self, cell: S, neighbours: &[S]) -> S;
+}
+
+impl<S, F: Fn(S, &[S]) -> S> GridRule<S> for F {
+    fn next(&self, cell: S, neighbours: &[S]) -> S {
+        self(cell, neighbours)
+    }
+}
+
+/// A rectangular grid updated into a second buffer, so every cell sees the previous
+/// generation.
+#[derive(Clone, Debug)]
+pub struct Grid<S, R> {
+    cells: Vec<S>,
+    next: Vec<S>,
+    neighbours: Vec<S>,
+    rows: usize,
+    cols: usize,
+    offsets: Vec<(isize, isize)>,
+    edge: Edge<S>,
+    rule: R,
+    generation: usize
+}
+
+impl<S: Copy, R: GridRule<S>> Grid<S, R> {
+    pub fn new(rows: Vec<Vec<S>>, neighbourhood: Neighbourhood, edge: Edge<S>, rule: R) -> Grid<S, R> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        assert!(rows.iter().all(|row| row.len() == cols), "every row must be the same length");
+
+        let num_rows = rows.len();
+        let cells = rows.into_iter().flatten().collect::<Vec<_>>();
+        Grid {
+            next: cells.clone(),
+            cells,
+            neighbours: Vec::new(),
+            rows: num_rows,
+            cols,
+            offsets: neighbourhood.offsets(),
+            edge,
+            rule,
+            generation: 0
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> S {
+        self.cells[row * self.cols + col]
+    }
+
+    /// All cells, row by row.
+    pub fn cells(&self) -> &[S] {
+        &self.cells
+    }
+}
+
+impl<S: Copy, R: GridRule<S>> Automaton for Grid<S, R> {
+    fn step(&mut self) {
+        let (rows, cols) = (self.rows as isize, self.cols as isize);
+
+        for row in 0..rows {
+            for col in 0..cols {
+                self.neighbours.clear();
+                for &(dr, dc) in &self.offsets {
+                    let (r, c) = (row + dr, col + dc);
+                    let inside = (0..rows).contains(&r) && (0..cols).contains(&c);
+                    let neighbour = match self.edge {
+                        _ if inside => Some(self.cells[(r * cols + c) as usize]),
+                        Edge::Omit => None,
+                        Edge::Wrap => Some(self.cells[(r.rem_euclid(rows) * cols + c.rem_euclid(cols)) as usize]),
+                        Edge::Constant(state) => Some(state)
+                    };
+                    self.neighbours.extend(neighbour);
+                }
+
+                let i = (row * cols + col) as usize;
+                self.next[i] = self.rule.next(self.cells[i], &self.neighbours);
+            }
+        }
+
+        std::mem::swap(&mut self.cells, &mut self.next);
+        self.generation += 1;
+    }
+
+    fn generation(&self) -> usize {
+        self.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show<R: LineRule<bool>>(line: &Line<bool, R>, from: isize, to: isize) -> String {
+        (from..=to).map(|i| if line.get(i) { '#' } else { '.' }).collect()
+    }
+
+    #[test]
+    fn test_line() {
+        // Rule 90: each cell becomes the exclusive or of its neighbours.
+        let rule = RuleTable::new(1, (0..8).map(|window| (window, (90 >> window) & 1 == 1)));
+        let line = Line::new(vec![true], 0, false, 1, rule);
+
+        let rows = line.generations()
+            .take(4)
+            .map(|line| show(&line, -3, 3))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, ["...#...", "..#.#..", ".#...#.", "#.#.#.#"]);
+
+        // Closures work as rules too, and the stored stretch follows the live cells.
+        let mut line = Line::new(vec![0, 0, 5, 0], 10, 0, 1, |w: &[u8]| w[0]);
+        line.step();
+        line.step();
+        assert_eq!(line.cells().collect::<Vec<_>>(), [(14, 5)]);
+        assert_eq!(line.generation(), 2);
+    }
+
+    #[test]
+    fn test_grid() {
+        let life = |cell: bool, neighbours: &[bool]| {
+            let alive = neighbours.iter().filter(|&&n| n).count();
+            alive == 3 || (cell && alive == 2)
+        };
+        let parse = |rows: &str| rows.lines()
+            .map(|row| row.chars().map(|c| c == '#').collect())
+            .collect::<Vec<Vec<bool>>>();
+
+        // A glider on a torus is back where it started after crossing it.
+        let glider = parse(".#....\n..#...\n###...\n......\n......\n......");
+        let mut grid = Grid::new(glider, Neighbourhood::Moore(1), Edge::Wrap, life);
+        (0..24).for_each(|_| grid.step());
+        assert_eq!(grid.cells(), parse(".#....\n..#...\n###...\n......\n......\n......").concat());
+
+        // A blinker along the edge only grows inwards when there is nothing beyond.
+        let mut blinker = Grid::new(parse("###\n...\n..."), Neighbourhood::Moore(1), Edge::Omit, life);
+        blinker.step();
+        assert_eq!(blinker.cells(), parse(".#.\n.#.\n...").concat());
+
+        // Each corner of a 2x2 grid sees five cells beyond the edge.
+        let mut count = Grid::new(vec![vec![0; 2]; 2], Neighbourhood::Moore(1), Edge::Constant(1), |_, n: &[u8]| n.iter().sum());
+        count.step();
+        assert_eq!(count.cells(), [5; 4]);
+        assert_eq!((count.rows(), count.cols(), count.get(1, 1)), (2, 2, 5));
+
+        assert_eq!(Neighbourhood::VonNeumann(1).offsets(), [(-1, 0), (0, -1), (0, 1), (1, 0)]);
+        assert_eq!(Neighbourhood::Moore(2).offsets().len(), 24);
+    }
+}
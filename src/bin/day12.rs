@@ -1,4 +1,6 @@
+use adventofcode2018::automaton::{Automaton, Line, RuleTable};
 use adventofcode2018::build_main;
+use adventofcode2018::cycle::Pattern;
 use itertools::Itertools;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
@@ -7,76 +9,34 @@ use nom::combinator::{map, value};
 use nom::multi::{many1, separated_list1};
 use nom::sequence::{preceded, separated_pair};
 use nom::IResult;
-use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet};
 
+/// Pots along an unbounded line, each with or without a plant.
 struct Cave {
-    has_plant: HashSet<isize>,
-    rules: HashMap<u8, bool>,
-    min_seen: isize,
-    max_seen: isize
-}
-
-impl Cave {
-    fn get_mask(&self, center: isize) -> u8 {
-        (center-2..=center+2).map(|j| if self.has_plant.contains(&j) { 1 } else { 0 })
-            .fold(0, |cur, i| 2 * cur + i)
-    }
-
-    fn activate(&mut self, i: isize) {
-        self.has_plant.insert(i);
-        self.min_seen = min(self.min_seen, i);
-        self.max_seen = max(self.max_seen, i);
-    }
-
-    fn deactivate(&mut self, i: isize) {
-        self.has_plant.remove(&i);
-    }
-
-    fn update(&mut self) {
-        let mut to_activate = Vec::new();
-        let mut to_deactivate = Vec::new();
-        (self.min_seen - 2..=self.max_seen + 2).for_each(|i| {
-            if self.rules[&self.get_mask(i)] {
-                to_activate.push(i);
-            } else {
-                to_deactivate.push(i);
-            }
-        });
-
-        to_activate.into_iter().for_each(|i| self.activate(i));
-        to_deactivate.into_iter().for_each(|i| self.deactivate(i));
-    }
+    pots: Line<bool, RuleTable>
 }
 
 impl Iterator for Cave {
     type Item = isize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = Some(self.has_plant.iter().cloned().sum());
-        self.update();
+        let result = Some(self.pots.cells().filter(|&(_, plant)| plant).map(|(i, _)| i).sum());
+        self.pots.step();
         result
     }
 }
 
 fn parse_input(input: &str) -> IResult<&str, Cave> {
-    fn initial_state(input: &str) -> IResult<&str, HashSet<isize>> {
-        map(
-            preceded(
-                tag("initial state: "),
-                many1(alt((
-                    value(true, tag("#")),
-                    value(false, tag("."))
-                )))
-            ),
-            |v| v.into_iter().enumerate()
-                .filter(|&(_, plant)| plant)
-                .map(|(i, _)| i as isize)
-                .collect()
+    fn initial_state(input: &str) -> IResult<&str, Vec<bool>> {
+        preceded(
+            tag("initial state: "),
+            many1(alt((
+                value(true, tag("#")),
+                value(false, tag("."))
+            )))
         )(input)
     }
 
-    fn rule(input: &str) -> IResult<&str, (u8, bool)> {
+    fn rule(input: &str) -> IResult<&str, (usize, bool)> {
         separated_pair(
             map(
                 many1(alt((
@@ -101,13 +61,7 @@ fn parse_input(input: &str) -> IResult<&str, Cave> {
             multispace1,
             separated_list1(newline, rule)
         ),
-        |(has_plant, rules_list)| {
-            let rules = rules_list.into_iter().collect();
-            let min_seen = *has_plant.iter().min().unwrap();
-            let max_seen = *has_plant.iter().max().unwrap();
-
-            Cave { has_plant, rules, min_seen, max_seen }
-        }
+        |(pots, rules)| Cave { pots: Line::new(pots, 0, false, 2, RuleTable::new(2, rules)) }
     )(input)
 }
 
@@ -117,29 +71,43 @@ fn part1(input: &str) -> isize {
         .unwrap()
 }
 
-fn line_coeffs(data: &[isize]) -> Option<(isize, isize)> {
-    let b = data[0];
-    let a = data[1] - data[0];
-
-    if data.into_iter().enumerate()
-        .all(|(i, &value)| value == a * (i as isize) + b) {
-        Some((a, b))
-    } else { None }
-}
-
 fn part2(input: &str) -> isize {
     let cave = parse_input(input).unwrap().1;
 
-    let vals = cave.take(1000).collect_vec();
+    let sums = cave.take(1000).collect_vec();
+    Pattern::find(&sums, 100).unwrap().value_at(50000000000)
+}
 
-    let (from, (a, b)) = (0..=900).map(|i| (i, line_coeffs(&vals[i..i+100])))
-        .filter_map(|(i, coeffs)| {
-            coeffs.map(|cs| (i as isize, cs))
-        })
-        .next()
-        .unwrap();
+build_main!("day12.txt", "Part 1" => part1, "Part 2" => part2);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_INPUT: &str = "initial state: #..#.#..##......###...###
+
+...## => #
+..#.. => #
+.#... => #
+.#.#. => #
+.#.## => #
+.##.. => #
+.#### => #
+#.#.# => #
+#.### => #
+##.#. => #
+##.## => #
+###.. => #
+###.# => #
+####. => #";
+
+    #[test]
+    fn test_part1() {
+        assert_eq!(part1(TEST_INPUT), 325);
+    }
 
-    a * (50000000000 - from) + b
+    #[test]
+    fn test_part2() {
+        assert_eq!(part2(TEST_INPUT), 999999999374);
+    }
 }
-
-build_main!("day12.txt", "Part 1" => part1, "Part 2" => part2);
\ No newline at end of file
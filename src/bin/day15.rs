@@ -1,45 +1,119 @@
-use crate::Race::{Elf, Goblin};
-use crate::Step::*;
+use crate::Event::*;
 use adventofcode2018::build_main;
+use adventofcode2018::parallel::parallel_map;
+use adventofcode2018::rng::Rng;
 use itertools::Itertools;
 use nom::branch::alt;
-use nom::character::complete::{char as ch, newline};
-use nom::combinator::{all_consuming, map, value};
-use nom::multi::{many1, separated_list1};
+use nom::bytes::complete::tag;
+use nom::character::complete::{char as ch, digit1, multispace0, newline, satisfy, space1};
+use nom::combinator::{all_consuming, map, map_res, value};
+use nom::multi::{many0, many1, separated_list0, separated_list1};
+use nom::sequence::{preceded, separated_pair, terminated, tuple};
 use nom::IResult;
+use std::cmp::Reverse;
 use std::collections::{HashMap, VecDeque};
-use std::ops::Add;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 struct Pair(isize, isize);
 
-impl Add for Pair {
-    type Output = Self;
-    fn add(self, other: Self) -> Self {
-        Self(self.0 + other.0, self.1 + other.1)
+/// A side in the battle, identified by its letter on the map.  Every faction attacks all others.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+struct Faction(char);
+
+const ELF: Faction = Faction('E');
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct Stats {
+    hp: usize,
+    attack_power: usize
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+struct Overrides {
+    hp: Option<usize>,
+    attack_power: Option<usize>
+}
+
+impl Overrides {
+    fn apply(&self, stats: Stats) -> Stats {
+        Stats {
+            hp: self.hp.unwrap_or(stats.hp),
+            attack_power: self.attack_power.unwrap_or(stats.attack_power)
+        }
+    }
+
+    fn merge(&mut self, other: Overrides) {
+        self.hp = other.hp.or(self.hp);
+        self.attack_power = other.attack_power.or(self.attack_power);
     }
 }
 
-const UP: Pair = Pair(-1, 0);
-const DOWN: Pair = Pair(1, 0);
-const LEFT: Pair = Pair(0, -1);
-const RIGHT: Pair = Pair(0, 1);
-const DIRECTIONS: [Pair; 4] = [UP, LEFT, RIGHT, DOWN];
+/// Starting hit points and attack power for every unit.  Faction overrides apply on top of the
+/// defaults, and per-unit overrides, keyed by starting position, on top of those.
+#[derive(Clone, Debug)]
+struct CombatRules {
+    default: Stats,
+    factions: HashMap<Faction, Overrides>,
+    units: HashMap<Pair, Overrides>
+}
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum Race { Elf, Goblin }
+impl CombatRules {
+    /// Every unit starts with 200 hit points and 3 attack power, as in part 1.
+    fn standard() -> Self {
+        CombatRules {
+            default: Stats { hp: 200, attack_power: 3 },
+            factions: HashMap::new(),
+            units: HashMap::new()
+        }
+    }
+
+    /// The standard rules with elves hitting harder, as in part 2.
+    fn elf_attack(attack_power: usize) -> Self {
+        let mut rules = Self::standard();
+        rules.factions.insert(ELF, Overrides { hp: None, attack_power: Some(attack_power) });
+        rules
+    }
+
+    fn stats(&self, faction: Faction, position: Pair) -> Stats {
+        let stats = self.factions.get(&faction).map_or(self.default, |o| o.apply(self.default));
+        self.units.get(&position).map_or(stats, |o| o.apply(stats))
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 struct Warrior {
     hp: usize,
-    race: Race,
+    faction: Faction,
     attack_power: usize,
     position: Pair
 }
 
-enum Step {
-    WarriorTurn {
-        target: Option<Warrior>
+/// Something that happened in the battle.  Units are identified by their index in
+/// `Level::warriors`: reading order on the parsed map, then any units added afterwards.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Event {
+    Moved {
+        unit: usize,
+        from: Pair,
+        to: Pair
+    },
+    /// `damage` is the hit points actually lost, so it never exceeds what the target had left.
+    Attacked {
+        attacker: usize,
+        target: usize,
+        damage: usize,
+        remaining_hp: usize
+    },
+    Died {
+        unit: usize,
+        round: usize
+    },
+    RoundEnded {
+        round: usize
     },
     Done {
         completed_rounds: usize,
@@ -47,102 +121,250 @@ enum Step {
     }
 }
 
+/// Scratch space for the movement search, kept between turns so it is never reallocated.
+#[derive(Clone, Default)]
+struct Search {
+    /// A square has been reached in the current search iff `visited[i] == epoch`.
+    visited: Vec<u32>,
+    /// The first step taken on the way to each reached square.
+    via: Vec<usize>,
+    queue: Vec<usize>,
+    epoch: u32
+}
+
+impl Search {
+    fn start(&mut self, len: usize) {
+        if self.visited.len() != len || self.epoch == u32::MAX {
+            self.visited = vec![0; len];
+            self.via = vec![0; len];
+            self.epoch = 0;
+        }
+        self.epoch += 1;
+        self.queue.clear();
+    }
+
+    fn visit(&mut self, square: usize, via: usize) {
+        self.visited[square] = self.epoch;
+        self.via[square] = via;
+        self.queue.push(square);
+    }
+
+    fn is_visited(&self, square: usize) -> bool {
+        self.visited[square] == self.epoch
+    }
+}
+
+/// The cave.  Squares are indexed by `row * cols + col`, so comparing indices compares
+/// squares in reading order.
 #[derive(Clone)]
 struct Level {
-    is_wall: Vec<Vec<bool>>,
+    walls: Vec<bool>,
     rows: usize,
     cols: usize,
     warriors: Vec<Warrior>,
-    positions: HashMap<Pair, usize>,
+    /// The living warrior standing on each square.
+    occupants: Vec<Option<usize>>,
     cur_round: usize,
     turn_order: VecDeque<usize>,
-    is_done: bool
+    events: VecDeque<Event>,
+    is_done: bool,
+    search: Search
 }
 
 impl Level {
-    fn is_open(&self, pos: Pair) -> bool {
-        0 <= pos.0 && pos.0 < self.rows as isize && 0 <= pos.1 && pos.1 < self.cols as isize
-            && !self.positions.contains_key(&pos) && !self.is_wall[pos.0 as usize][pos.1 as usize]
+    fn index(&self, Pair(row, col): Pair) -> usize {
+        row as usize * self.cols + col as usize
     }
 
-    fn open_neighbors(&self, pos: Pair) -> Vec<Pair> {
-        DIRECTIONS.iter()
-            .map(|&d| pos + d)
-            .filter(|&p| self.is_open(p))
-            .collect_vec()
+    fn pair(&self, square: usize) -> Pair {
+        Pair((square / self.cols) as isize, (square % self.cols) as isize)
     }
 
-    fn enemy_positions(&self, pos: Pair) -> Vec<Pair> {
-        if !self.positions.contains_key(&pos) {
-            return Vec::new();
-        }
+    /// Orthogonal neighbours of a square, in reading order.
+    fn neighbors(&self, square: usize) -> impl Iterator<Item=usize> {
+        let (row, col) = (square / self.cols, square % self.cols);
+        [
+            (row > 0).then(|| square - self.cols),
+            (col > 0).then(|| square - 1),
+            (col + 1 < self.cols).then(|| square + 1),
+            (row + 1 < self.rows).then(|| square + self.cols)
+        ].into_iter().flatten()
+    }
 
-        let race = self.warriors[self.positions[&pos]].race;
+    fn is_open(&self, square: usize) -> bool {
+        !self.walls[square] && self.occupants[square].is_none()
+    }
 
-        self.positions.iter()
-            .filter(|&(_, &i)| {
-                let warrior = &self.warriors[i];
-                warrior.race != race
-            })
-            .map(|(&pos, _)| pos)
-            .collect_vec()
+    fn enemy_at(&self, square: usize, faction: Faction) -> Option<usize> {
+        self.occupants[square].filter(|&i| self.warriors[i].faction != faction)
     }
 
-    fn find_move(&self, pos: Pair) -> Option<Pair> {
-        let mut distances = HashMap::new();
-        let mut queue = VecDeque::new();
+    fn is_in_range(&self, square: usize, faction: Faction) -> bool {
+        self.neighbors(square).any(|n| self.enemy_at(n, faction).is_some())
+    }
 
-        distances.insert(pos, (0, None));
-        for nbr in self.open_neighbors(pos) {
-            distances.insert(nbr, (1, Some(nbr)));
-            queue.push_back((nbr, 1, nbr));
+    fn has_enemies(&self, faction: Faction) -> bool {
+        self.warriors.iter().any(|w| w.hp > 0 && w.faction != faction)
+    }
+
+    /// The first step towards the nearest square in range of an enemy, breaking ties by reading
+    /// order of the destination and then of the step.  The search goes one distance at a time
+    /// and stops at the first distance with any square in range.
+    fn find_move(&mut self, from: usize, faction: Faction) -> Option<usize> {
+        if self.is_in_range(from, faction) {
+            return None;
         }
 
-        while let Some((p, dist, via)) = queue.pop_front() {
-            for nbr in self.open_neighbors(p) {
-                if !distances.contains_key(&nbr) {
-                    distances.insert(nbr, (dist + 1, Some(via)));
-                    queue.push_back((nbr, dist + 1, via));
-                }
+        let mut search = mem::take(&mut self.search);
+        search.start(self.walls.len());
+
+        for n in self.neighbors(from) {
+            if self.is_open(n) {
+                search.visit(n, n);
             }
         }
 
-        self.enemy_positions(pos).into_iter()
-            .flat_map(|tgt| {
-                DIRECTIONS.map(|d| d + tgt)
-            })
-            .filter(|&tgt| tgt == pos || self.is_open(tgt))
-            .filter_map(|tgt| {
-                let &(dist, via) = distances.get(&tgt)?;
-                Some((dist, tgt, via))
-            })
+        let mut head = 0;
+        let step = loop {
+            let tail = search.queue.len();
+            if head == tail {
+                break None;
+            }
+
+            let nearest = search.queue[head..tail].iter()
+                .copied()
+                .filter(|&square| self.is_in_range(square, faction))
+                .min();
+            if let Some(square) = nearest {
+                break Some(search.via[square]);
+            }
+
+            for i in head..tail {
+                let square = search.queue[i];
+                for n in self.neighbors(square) {
+                    if self.is_open(n) && !search.is_visited(n) {
+                        search.visit(n, search.via[square]);
+                    }
+                }
+            }
+            head = tail;
+        };
+
+        self.search = search;
+        step
+    }
+
+    /// The adjacent enemy with the fewest hit points, first in reading order on a tie.
+    fn pick_attack(&self, square: usize, faction: Faction) -> Option<usize> {
+        self.neighbors(square)
+            .filter_map(|n| self.enemy_at(n, faction).map(|i| (self.warriors[i].hp, n)))
             .min()
-            .and_then(|(_, _, via)| via)
+            .map(|(_, n)| n)
     }
 
-    fn pick_attack(&self, pos: Pair) -> Option<Pair> {
-        let index = *self.positions.get(&pos)?;
-        assert!(self.warriors[index].hp > 0);
+    fn is_inside(&self, Pair(row, col): Pair) -> bool {
+        0 <= row && (row as usize) < self.rows && 0 <= col && (col as usize) < self.cols
+    }
 
-        let race = self.warriors[index].race;
+    /// Place a new unit on an open square before the battle starts, returning its id.
+    fn add_unit(&mut self, faction: Faction, position: Pair, stats: Stats) -> Option<usize> {
+        assert_eq!(self.cur_round, 0, "units can only be changed before the battle");
+        if !self.is_inside(position) || !self.is_open(self.index(position)) {
+            return None;
+        }
 
-        DIRECTIONS.iter()
-            .map(|&d| pos + d)
-            .filter_map(|p| {
-                let index = *self.positions.get(&p)?;
-                let enemy = &self.warriors[index];
-                if enemy.race != race { Some((enemy.hp, p)) }
-                else { None }
-            })
-            .min()
-            .map(|(_, p)| p)
+        let Stats { hp, attack_power } = stats;
+        self.warriors.push(Warrior { hp, faction, attack_power, position });
+        let id = self.warriors.len() - 1;
+        let square = self.index(position);
+        self.occupants[square] = Some(id);
+        Some(id)
     }
 
-    fn step(&mut self) -> Option<Step> {
-        if self.is_done {
+    /// Take the unit on `position` off the map before the battle starts.  Later units' ids
+    /// shift down by one.
+    fn remove_unit(&mut self, position: Pair) -> Option<Warrior> {
+        assert_eq!(self.cur_round, 0, "units can only be changed before the battle");
+        if !self.is_inside(position) {
             return None;
         }
 
+        let id = self.occupants[self.index(position)]?;
+        let warrior = self.warriors.remove(id);
+        self.occupants.iter_mut().for_each(|o| *o = o.filter(|&i| i != id).map(|i| if i > id { i - 1 } else { i }));
+        Some(warrior)
+    }
+
+    /// Turn an open square into a wall.  Returns whether anything changed.
+    fn add_wall(&mut self, position: Pair) -> bool {
+        if !self.is_inside(position) {
+            return false;
+        }
+
+        let square = self.index(position);
+        if !self.is_open(square) {
+            return false;
+        }
+        self.walls[square] = true;
+        true
+    }
+
+    /// Turn a wall into an open square.  Returns whether anything changed.
+    fn remove_wall(&mut self, position: Pair) -> bool {
+        if !self.is_inside(position) {
+            return false;
+        }
+
+        let square = self.index(position);
+        if !self.walls[square] {
+            return false;
+        }
+        self.walls[square] = false;
+        true
+    }
+
+    /// Fight the battle to the end.
+    fn fight(&mut self) -> Outcome {
+        for event in self.by_ref() {
+            if let Done { completed_rounds, total_hp } = event {
+                return Outcome { completed_rounds, total_hp };
+            }
+        }
+
+        unreachable!()
+    }
+
+    /// Fight the battle once for every open square with an extra unit of `faction` standing
+    /// there, ranked best first for that faction.  Ranking by score puts the battles the
+    /// faction wins ahead of the ones it loses, since the score belongs to whoever is left.
+    fn placement_sweep(&self, faction: Faction, stats: Stats, ranking: Ranking) -> Vec<Placement> {
+        let mut placements = (0..self.walls.len())
+            .filter(|&square| self.is_open(square))
+            .map(|square| {
+                let position = self.pair(square);
+                let mut level = self.clone();
+                level.add_unit(faction, position, stats);
+                let outcome = level.fight();
+                let survivors = level.warriors.iter()
+                    .filter(|w| w.hp > 0 && w.faction == faction)
+                    .count();
+                Placement { position, outcome, survivors }
+            })
+            .collect_vec();
+
+        match ranking {
+            Ranking::Score => placements.sort_by_key(|p| (Reverse(p.won()), Reverse(p.outcome.score()), Reverse(p.survivors), p.position)),
+            Ranking::Survivors => placements.sort_by_key(|p| (Reverse(p.survivors), Reverse(p.outcome.score()), p.position))
+        }
+        placements
+    }
+
+    /// Whether every unit still due to act this round has died, so the round is over.
+    fn is_round_complete(&self) -> bool {
+        self.turn_order.iter().all(|&i| self.warriors[i].hp == 0)
+    }
+
+    fn take_turn(&mut self) {
         if self.turn_order.is_empty() {
             self.turn_order = self.warriors.iter().enumerate()
                 .filter(|&(_, &w)| w.hp > 0)
@@ -155,115 +377,270 @@ impl Level {
 
         let warrior_id = self.turn_order.pop_front().unwrap();
         if self.warriors[warrior_id].hp == 0 {
-            return self.step();
+            return;
         }
 
-        if self.enemy_positions(self.warriors[warrior_id].position).is_empty() {
+        let faction = self.warriors[warrior_id].faction;
+
+        if !self.has_enemies(faction) {
             let completed_rounds = self.cur_round - 1;
             let total_hp = self.warriors.iter().map(|warrior| warrior.hp).sum::<usize>();
             self.is_done = true;
-            return Some(Done { completed_rounds, total_hp })
+            self.events.push_back(Done { completed_rounds, total_hp });
+            return;
         }
 
-        let from = self.warriors[warrior_id].position;
-        let to = self.find_move(from);
+        let mut square = self.index(self.warriors[warrior_id].position);
 
-        if let Some(p) = to {
-            self.warriors[warrior_id].position = p;
-            self.positions.remove(&from);
-            self.positions.insert(p, warrior_id);
+        if let Some(to) = self.find_move(square, faction) {
+            let from = self.warriors[warrior_id].position;
+            self.occupants[square] = None;
+            self.occupants[to] = Some(warrior_id);
+            self.warriors[warrior_id].position = self.pair(to);
+            self.events.push_back(Moved { unit: warrior_id, from, to: self.pair(to) });
+            square = to;
         }
 
-
-        let position = self.warriors[warrior_id].position;
-        let target_position = self.pick_attack(position);
-
-        let target = target_position.map(|tpos| {
-            let tid = self.positions[&tpos];
+        if let Some(target_square) = self.pick_attack(square, faction) {
+            let tid = self.occupants[target_square].unwrap();
             let attack_power = self.warriors[warrior_id].attack_power;
-            let cur_hp = self.warriors[tid].hp;
+            let damage = attack_power.min(self.warriors[tid].hp);
 
-            if cur_hp > attack_power {
-                self.warriors[tid].hp -= attack_power;
+            self.warriors[tid].hp -= damage;
+            self.events.push_back(Attacked {
+                attacker: warrior_id,
+                target: tid,
+                damage,
+                remaining_hp: self.warriors[tid].hp
+            });
+
+            if self.warriors[tid].hp == 0 {
+                self.occupants[target_square] = None;
+                self.events.push_back(Died { unit: tid, round: self.cur_round });
             }
-            else {
-                self.warriors[tid].hp = 0;
-                self.positions.remove(&tpos);
+        }
+
+        if self.is_round_complete() {
+            self.events.push_back(RoundEnded { round: self.cur_round });
+        }
+    }
+
+    fn step(&mut self) -> Option<Event> {
+        while self.events.is_empty() {
+            if self.is_done {
+                return None;
             }
+            self.take_turn();
+        }
 
-            self.warriors[tid]
-        });
+        self.events.pop_front()
+    }
 
-        let result = Some(WarriorTurn { target });
+    /// One line of JSON describing `event`, with the faction of each unit involved.
+    fn event_json(&self, event: &Event) -> String {
+        let faction = |unit: usize| self.warriors[unit].faction.0;
+        let pair = |Pair(row, col): Pair| format!("[{row},{col}]");
 
-        result
+        match *event {
+            Moved { unit, from, to } => format!(
+                r#"{{"event":"moved","unit":{unit},"faction":"{}","from":{},"to":{}}}"#,
+                faction(unit), pair(from), pair(to)
+            ),
+            Attacked { attacker, target, damage, remaining_hp } => format!(
+                r#"{{"event":"attacked","attacker":{attacker},"attacker_faction":"{}","target":{target},"target_faction":"{}","damage":{damage},"remaining_hp":{remaining_hp}}}"#,
+                faction(attacker), faction(target)
+            ),
+            Died { unit, round } => format!(
+                r#"{{"event":"died","unit":{unit},"faction":"{}","round":{round}}}"#,
+                faction(unit)
+            ),
+            RoundEnded { round } => format!(r#"{{"event":"round_ended","round":{round}}}"#),
+            Done { completed_rounds, total_hp } => format!(
+                r#"{{"event":"done","completed_rounds":{completed_rounds},"total_hp":{total_hp}}}"#
+            )
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for row in 0..self.rows {
+            let mut units = Vec::new();
+
+            for square in row * self.cols..(row + 1) * self.cols {
+                let c = match self.occupants[square] {
+                    _ if self.walls[square] => '#',
+                    Some(id) => {
+                        let warrior = &self.warriors[id];
+                        let Faction(c) = warrior.faction;
+                        units.push(format!("{c}({})", warrior.hp));
+                        c
+                    },
+                    None => '.'
+                };
+                write!(f, "{c}")?;
+            }
+
+            if !units.is_empty() {
+                write!(f, "   {}", units.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
 impl Iterator for Level {
-    type Item = Step;
+    type Item = Event;
     fn next(&mut self) -> Option<Self::Item> {
         self.step()
     }
 }
 
 
-fn parse_input(input: &str) -> IResult<&str, Level> {
-    fn space(input: &str) -> IResult<&str, (Option<Race>, bool)> {
+fn parse_input<'a>(input: &'a str, rules: &CombatRules) -> IResult<&'a str, Level> {
+    fn space(input: &str) -> IResult<&str, (Option<Faction>, bool)> {
         alt((
             value((None, false), ch('.')),
             value((None, true), ch('#')),
-            value((Some(Elf), false), ch('E')),
-            value((Some(Goblin), false), ch('G'))
+            map(satisfy(|c| c.is_ascii_uppercase()), |c| (Some(Faction(c)), false))
         ))(input)
     }
 
     map(
         all_consuming(separated_list1(newline, many1(space))),
         |v| {
+            let rows = v.len();
+            let cols = v[0].len();
             let mut warriors = Vec::new();
-            let mut positions = HashMap::new();
+            let mut occupants = vec![None; rows * cols];
 
             v.iter().enumerate().for_each(|(i, row)| {
                 row.iter().enumerate().for_each(|(j, &(warrior_type, _))| {
-                    if let Some(race) = warrior_type {
+                    if let Some(faction) = warrior_type {
                         let position = Pair(i as isize, j as isize);
-                        let warrior = Warrior { hp: 200, race, attack_power: 3, position };
+                        let Stats { hp, attack_power } = rules.stats(faction, position);
+                        let warrior = Warrior { hp, faction, attack_power, position };
                         warriors.push(warrior);
-                        let id = warriors.len() - 1;
-                        positions.insert(position, id);
+                        occupants[i * cols + j] = Some(warriors.len() - 1);
                     }
 
                 })
             });
 
-            let is_wall = v.into_iter()
-                .map(|row| row.into_iter().map(|(_, b)| b).collect_vec())
+            let walls = v.into_iter()
+                .flat_map(|row| row.into_iter().map(|(_, b)| b))
                 .collect_vec();
 
-            let rows = is_wall.len();
-            let cols = is_wall[0].len();
-
             Level {
-                is_wall,
+                walls,
                 rows,
                 cols,
                 warriors,
-                positions,
+                occupants,
                 cur_round: 0,
                 turn_order: VecDeque::new(),
-                is_done: false
+                events: VecDeque::new(),
+                is_done: false,
+                search: Search::default()
             }
         }
     )(input)
 }
 
-fn part1(input: &str) -> usize {
-    let mut level = parse_input(input).unwrap().1;
+/// Parse a side file of overrides applied on top of `rules`.  Each line names a faction by its
+/// map letter, or a single unit by its starting `row,col`, followed by any of `hp N` and
+/// `attack N`, e.g. `E attack 10` or `4,5 hp 50 attack 20`.
+fn parse_rules(input: &str, mut rules: CombatRules) -> IResult<&str, CombatRules> {
+    #[derive(Copy, Clone)]
+    enum Subject {
+        Faction(Faction),
+        Unit(Pair)
+    }
+
+    fn number(input: &str) -> IResult<&str, usize> {
+        map_res(digit1, |s: &str| s.parse::<usize>())(input)
+    }
+
+    fn subject(input: &str) -> IResult<&str, Subject> {
+        alt((
+            map(satisfy(|c| c.is_ascii_uppercase()), |c| Subject::Faction(Faction(c))),
+            map(
+                separated_pair(number, ch(','), number),
+                |(row, col)| Subject::Unit(Pair(row as isize, col as isize))
+            )
+        ))(input)
+    }
+
+    fn setting(input: &str) -> IResult<&str, Overrides> {
+        alt((
+            map(preceded(tag("hp "), number), |hp| Overrides { hp: Some(hp), attack_power: None }),
+            map(preceded(tag("attack "), number), |attack_power| Overrides { hp: None, attack_power: Some(attack_power) })
+        ))(input)
+    }
+
+    let line = tuple((subject, many0(preceded(space1, setting))));
+
+    let (rest, lines) = all_consuming(terminated(separated_list0(newline, line), multispace0))(input)?;
 
-    while let Some(step) = level.next() {
-        match step {
-            Done { completed_rounds, total_hp } => return completed_rounds * total_hp,
+    for (subject, settings) in lines {
+        let overrides = match subject {
+            Subject::Faction(faction) => rules.factions.entry(faction).or_default(),
+            Subject::Unit(position) => rules.units.entry(position).or_default()
+        };
+        settings.into_iter().for_each(|s| overrides.merge(s));
+    }
+
+    Ok((rest, rules))
+}
+
+/// The battlefield initially and after every full round, headed the way the puzzle text does.
+fn frames(mut level: Level) -> Vec<String> {
+    let mut frames = vec![format!("Initially:\n{level}")];
+
+    while let Some(event) = level.next() {
+        if let RoundEnded { round } = event {
+            let plural = if round == 1 { "" } else { "s" };
+            frames.push(format!("After {round} round{plural}:\n{level}"));
+        }
+    }
+
+    frames
+}
+
+fn replay(input: &str, _args: &[String]) {
+    let level = parse_input(input, &CombatRules::standard()).unwrap().1;
+    println!("{}", frames(level).join("\n"));
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct Outcome {
+    completed_rounds: usize,
+    total_hp: usize
+}
+
+impl Outcome {
+    fn score(&self) -> usize {
+        self.completed_rounds * self.total_hp
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct PowerSearch {
+    attack_power: usize,
+    outcome: Outcome
+}
+
+/// Fight with elves at `attack_power`, abandoning the battle as soon as an elf dies or
+/// `give_up` returns true.
+fn fight_without_elf_losses(input: &str, attack_power: usize, give_up: impl Fn() -> bool) -> Option<Outcome> {
+    let mut level = parse_input(input, &CombatRules::elf_attack(attack_power)).unwrap().1;
+
+    while let Some(event) = level.next() {
+        match event {
+            Done { completed_rounds, total_hp } => return Some(Outcome { completed_rounds, total_hp }),
+            Died { unit, .. } if level.warriors[unit].faction == ELF => return None,
+            _ if give_up() => return None,
             _ => ()
         }
     }
@@ -271,53 +648,251 @@ fn part1(input: &str) -> usize {
     unreachable!()
 }
 
-fn part2(input: &str) -> usize {
-    let level = parse_input(input).unwrap().1;
+/// The smallest elf attack power, from 4 up, at which no elf dies.
+///
+/// Winning is not guaranteed to be monotonic in attack power, so every power below the answer
+/// is fought out rather than bisected.  Threads take powers in increasing order and skip them
+/// once someone has won at a lower power; battles are abandoned at the first elf death.  Past
+/// the largest enemy hit points every blow kills outright and more power changes nothing, so
+/// the search ends there.
+fn search_elf_attack_power(input: &str) -> Option<PowerSearch> {
+    let level = parse_input(input, &CombatRules::standard()).unwrap().1;
+    let max_power = level.warriors.iter()
+        .filter(|w| w.faction != ELF)
+        .map(|w| w.hp)
+        .max()
+        .unwrap_or(0)
+        .max(4);
 
-    fn test(level: &Level, attack_power: usize) -> Option<usize> {
-        let mut level_mod = level.clone();
-        level_mod.warriors.iter_mut().for_each(|w| {
-            if w.race == Elf {
-                w.attack_power = attack_power;
+    let best = AtomicUsize::new(usize::MAX);
+    let powers = (4..=max_power).collect_vec();
+
+    parallel_map(&powers, |&attack_power| {
+        if attack_power > best.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let give_up = || best.load(Ordering::Relaxed) < attack_power;
+        let outcome = fight_without_elf_losses(input, attack_power, give_up)?;
+        best.fetch_min(attack_power, Ordering::Relaxed);
+        Some(PowerSearch { attack_power, outcome })
+    })
+    .into_iter()
+    .flatten()
+    .next()
+}
+
+/// A random cave in puzzle format: a walled border, interior walls at roughly `wall_density`,
+/// and the requested number of units of each faction.  Only the largest open region is kept,
+/// so every unit can reach every other one.
+fn generate_cave(rng: &mut Rng, rows: usize, cols: usize, wall_density: f64, units: &[(Faction, usize)]) -> String {
+    let mut grid = (0..rows)
+        .map(|i| (0..cols)
+            .map(|j| {
+                let border = i == 0 || j == 0 || i == rows - 1 || j == cols - 1;
+                if border || rng.chance(wall_density) { '#' } else { '.' }
+            })
+            .collect_vec())
+        .collect_vec();
+
+    let mut region = vec![vec![usize::MAX; cols]; rows];
+    let mut regions: Vec<Vec<(usize, usize)>> = Vec::new();
+
+    for i in 0..rows {
+        for j in 0..cols {
+            if grid[i][j] == '#' || region[i][j] != usize::MAX {
+                continue;
             }
-        });
-
-        while let Some(step) = level_mod.next() {
-            match step {
-                Done { completed_rounds, total_hp } => return Some(completed_rounds * total_hp),
-                WarriorTurn { target: Some(target) } => {
-                    if target.hp == 0 && target.race == Elf {
-                        return None
+
+            let mut squares = vec![(i, j)];
+            region[i][j] = regions.len();
+            let mut head = 0;
+            while head < squares.len() {
+                let (r, c) = squares[head];
+                head += 1;
+                for (nr, nc) in [(r - 1, c), (r, c - 1), (r, c + 1), (r + 1, c)] {
+                    if grid[nr][nc] == '.' && region[nr][nc] == usize::MAX {
+                        region[nr][nc] = regions.len();
+                        squares.push((nr, nc));
                     }
-                },
-                _ => ()
+                }
             }
+            regions.push(squares);
         }
+    }
 
-        unreachable!()
+    let largest = regions.iter().position_max_by_key(|r| r.len());
+    for (k, squares) in regions.iter().enumerate() {
+        if Some(k) != largest {
+            squares.iter().for_each(|&(r, c)| grid[r][c] = '#');
+        }
     }
 
-    let mut low = 4;
-    let mut high = 200;
+    let mut open = largest.map_or(Vec::new(), |k| regions[k].clone());
+    for &(Faction(c), count) in units {
+        for _ in 0..count {
+            if open.is_empty() {
+                break;
+            }
+            let (r, c2) = open.swap_remove(rng.below(open.len()));
+            grid[r][c2] = c;
+        }
+    }
+
+    grid.into_iter().map(|row| row.into_iter().collect::<String>()).join("\n")
+}
+
+/// Fight the battle to the end, checking after every event that no two units share a square,
+/// that the dead are off the map, that the event log accounts for every lost hit point, and that
+/// the battle ends within `max_rounds`.
+fn check_battle(mut level: Level, max_rounds: usize) -> Result<Outcome, String> {
+    let starting_hp = level.warriors.iter().map(|w| w.hp).collect_vec();
+    let mut damage_logged = 0;
+
+    while let Some(event) = level.next() {
+        match event {
+            Attacked { target, damage, remaining_hp, .. } => {
+                damage_logged += damage;
+                if level.warriors[target].hp != remaining_hp {
+                    return Err(format!("unit {target} has {} hp but the log says {remaining_hp}", level.warriors[target].hp));
+                }
+            },
+            Done { completed_rounds, total_hp } => {
+                if starting_hp.iter().sum::<usize>() != total_hp + damage_logged {
+                    return Err(format!("{total_hp} hp left after {damage_logged} damage logged"));
+                }
+                return Ok(Outcome { completed_rounds, total_hp });
+            },
+            _ => ()
+        }
 
-    while low < high {
-        let power = low + (high - low) / 2;
-        let result = test(&level, power);
-        if result.is_some() && test(&level, power - 1).is_none() {
-            return result.unwrap()
+        if level.cur_round > max_rounds {
+            return Err(format!("still fighting after {max_rounds} rounds"));
         }
-        else if result.is_some() {
-            high = power - 1;
+
+        for (i, w) in level.warriors.iter().enumerate() {
+            let square = level.index(w.position);
+            let on_map = level.occupants[square] == Some(i);
+            if w.hp > starting_hp[i] || on_map != (w.hp > 0) || level.walls[square] {
+                return Err(format!("unit {i} is inconsistent: {w:?}"));
+            }
         }
-        else {
-            low = power + 1;
+
+        let occupied = level.occupants.iter().filter(|o| o.is_some()).count();
+        let alive = level.warriors.iter().filter(|w| w.hp > 0).count();
+        if occupied != alive {
+            return Err(format!("{alive} units alive but {occupied} squares occupied"));
         }
     }
 
-    unreachable!()
+    Err("battle ended without an outcome".to_string())
 }
 
-build_main!("day15.txt", "Part 1" => part1, "Part 2" => part2);
+/// Fight randomly generated battles and report any that break an invariant.  Takes the number
+/// of battles and a starting seed.
+fn fuzz(_input: &str, args: &[String]) {
+    let count = args.first().map_or(1000, |s| s.parse().unwrap());
+    let seed = args.get(1).map_or(0, |s| s.parse().unwrap());
+
+    for seed in seed..seed + count {
+        let cave = random_cave(seed);
+        let level = parse_input(&cave, &CombatRules::standard()).unwrap().1;
+        if let Err(e) = check_battle(level, 10_000) {
+            println!("seed {seed}: {e}\n{cave}\n");
+        }
+    }
+}
+
+/// A cave of random size, density and armies for the given seed.
+fn random_cave(seed: u64) -> String {
+    let mut rng = Rng::new(seed);
+    let rows = 5 + rng.below(12);
+    let cols = 5 + rng.below(12);
+    let wall_density = rng.below(35) as f64 / 100.0;
+    let units = [(ELF, 1 + rng.below(5)), (Faction('G'), 1 + rng.below(5)), (Faction('H'), rng.below(3))];
+    generate_cave(&mut rng, rows, cols, wall_density, &units)
+}
+
+/// The result of fighting with one extra unit placed at `position`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct Placement {
+    position: Pair,
+    outcome: Outcome,
+    /// Units of the placed unit's faction alive at the end, including it.
+    survivors: usize
+}
+
+impl Placement {
+    fn won(&self) -> bool {
+        self.survivors > 0
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Ranking {
+    Score,
+    Survivors
+}
+
+/// List where an extra unit would change the part 1 battle the most.  Takes the faction letter
+/// (default `E`), `score` or `survivors` to rank by (default `survivors`), and then any edits to
+/// make to the map first, like `add-wall=3,4`, `remove-wall=3,2` or `remove-unit=2,4`.
+fn what_if(input: &str, args: &[String]) {
+    let mut level = parse_input(input, &CombatRules::standard()).unwrap().1;
+
+    for edit in args.iter().skip(2) {
+        let (action, position) = edit.split_once('=').expect("edits look like add-wall=row,col");
+        let (row, col) = position.split_once(',').expect("positions look like row,col");
+        let position = Pair(row.parse().unwrap(), col.parse().unwrap());
+        let changed = match action {
+            "add-wall" => level.add_wall(position),
+            "remove-wall" => level.remove_wall(position),
+            "remove-unit" => level.remove_unit(position).is_some(),
+            other => panic!("Unknown edit: {other}")
+        };
+        assert!(changed, "{edit} does not apply to this map");
+    }
+
+    let faction = Faction(args.first().and_then(|s| s.chars().next()).unwrap_or('E'));
+    let ranking = match args.get(1).map(String::as_str) {
+        Some("score") => Ranking::Score,
+        _ => Ranking::Survivors
+    };
+
+    let stats = CombatRules::standard().default;
+    for placement in level.placement_sweep(faction, stats, ranking).iter().take(10) {
+        let Pair(row, col) = placement.position;
+        println!("{row},{col}: {} survivors, outcome {}", placement.survivors, placement.outcome.score());
+    }
+}
+
+/// Fight the battle with the standard rules overridden by the side file named in `args`.
+fn custom_rules(input: &str, args: &[String]) {
+    let path = args.first().expect("usage: --rules <file>");
+    let side_file = std::fs::read_to_string(path).unwrap();
+    let rules = parse_rules(&side_file, CombatRules::standard()).unwrap().1;
+    let mut level = parse_input(input, &rules).unwrap().1;
+    println!("Outcome: {}", level.fight().score());
+}
+
+/// Print the part 1 battle as JSON lines, one event per line.
+fn events(input: &str, _args: &[String]) {
+    let mut level = parse_input(input, &CombatRules::standard()).unwrap().1;
+
+    while let Some(event) = level.next() {
+        println!("{}", level.event_json(&event));
+    }
+}
+
+fn part1(input: &str) -> usize {
+    parse_input(input, &CombatRules::standard()).unwrap().1.fight().score()
+}
+
+fn part2(input: &str) -> usize {
+    search_elf_attack_power(input).unwrap().outcome.score()
+}
+
+build_main!("day15.txt", "Part 1" => part1, "Part 2" => part2; "--replay" => replay, "--rules" => custom_rules, "--events" => events, "--fuzz" => fuzz, "--what-if" => what_if);
 
 #[cfg(test)]
 mod tests {
@@ -365,6 +940,147 @@ mod tests {
 #.....G.#
 #########";
 
+    const MIXED_SWEEP: &str = "#####
+#..##
+#.#.#
+#EGG#
+#EE.#
+#G.G#
+##EG#
+#####";
+
+    #[test]
+    fn test_frames() {
+        let frames = frames(parse_input(TEST_INPUT_1, &CombatRules::standard()).unwrap().1);
+        assert_eq!(frames.len(), 48);
+
+        assert_eq!(frames[1], "After 1 round:
+#######
+#..G..#   G(200)
+#...EG#   E(197), G(197)
+#.#G#G#   G(200), G(197)
+#...#E#   E(197)
+#.....#
+#######
+");
+
+        assert_eq!(frames[2], "After 2 rounds:
+#######
+#...G.#   G(200)
+#..GEG#   G(200), E(188), G(194)
+#.#.#G#   G(194)
+#...#E#   E(194)
+#.....#
+#######
+");
+
+        assert_eq!(frames[47], "After 47 rounds:
+#######
+#G....#   G(200)
+#.G...#   G(131)
+#.#.#G#   G(59)
+#...#.#
+#....G#   G(200)
+#######
+");
+    }
+
+    #[test]
+    fn test_factions() {
+        let input = "#####
+#EGH#
+#####";
+        let mut level = parse_input(input, &CombatRules::standard()).unwrap().1;
+        assert_eq!(level.fight().score(), 67 * 101);
+
+        let rules = parse_rules("G hp 20
+H attack 5 hp 100
+1,1 attack 10
+", CombatRules::standard()).unwrap().1;
+        assert_eq!(rules.stats(Faction('G'), Pair(1, 2)), Stats { hp: 20, attack_power: 3 });
+        assert_eq!(rules.stats(Faction('H'), Pair(1, 3)), Stats { hp: 100, attack_power: 5 });
+        assert_eq!(rules.stats(ELF, Pair(1, 1)), Stats { hp: 200, attack_power: 10 });
+        assert_eq!(rules.stats(ELF, Pair(2, 2)), Stats { hp: 200, attack_power: 3 });
+    }
+
+    #[test]
+    fn test_events() {
+        let mut level = parse_input(TEST_INPUT_1, &CombatRules::standard()).unwrap().1;
+        let round1 = level.by_ref().take(7).collect_vec();
+
+        assert_eq!(round1, [
+            Moved { unit: 0, from: Pair(1, 2), to: Pair(1, 3) },
+            Attacked { attacker: 1, target: 2, damage: 3, remaining_hp: 197 },
+            Attacked { attacker: 2, target: 1, damage: 3, remaining_hp: 197 },
+            Attacked { attacker: 3, target: 5, damage: 3, remaining_hp: 197 },
+            Moved { unit: 4, from: Pair(4, 3), to: Pair(3, 3) },
+            Attacked { attacker: 5, target: 3, damage: 3, remaining_hp: 197 },
+            RoundEnded { round: 1 }
+        ]);
+        assert_eq!(
+            level.event_json(&round1[0]),
+            r#"{"event":"moved","unit":0,"faction":"G","from":[1,2],"to":[1,3]}"#
+        );
+
+        let (died, rest): (Vec<_>, Vec<_>) = level.by_ref()
+            .partition(|event| matches!(event, Died { .. }));
+        assert_eq!(died.len(), 2);
+        assert_eq!(level.event_json(&died[0]), r#"{"event":"died","unit":1,"faction":"E","round":23}"#);
+        assert_eq!(rest.last(), Some(&Done { completed_rounds: 47, total_hp: 590 }));
+    }
+
+    #[test]
+    fn test_random_battles() {
+        for seed in 0..200 {
+            let cave = random_cave(seed);
+            let level = parse_input(&cave, &CombatRules::standard()).unwrap().1;
+            if let Err(e) = check_battle(level, 10_000) {
+                panic!("seed {seed}: {e}\n{cave}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_what_if() {
+        let mut level = parse_input(TEST_INPUT_1, &CombatRules::standard()).unwrap().1;
+        let stats = Stats { hp: 200, attack_power: 3 };
+
+        assert_eq!(level.add_unit(ELF, Pair(1, 1), stats), Some(6));
+        assert_eq!(level.add_unit(ELF, Pair(1, 2), stats), None);
+        assert!(level.remove_unit(Pair(2, 4)).is_some());
+        assert!(level.add_wall(Pair(5, 1)));
+        assert!(!level.add_wall(Pair(1, 2)));
+        assert!(level.remove_wall(Pair(3, 2)));
+        assert_eq!(level.to_string(), "#######
+#EG...#   E(200), G(200)
+#....G#   G(200)
+#...#G#   G(200)
+#..G#E#   G(200), E(200)
+##....#
+#######
+");
+        assert_eq!(level.occupants[level.index(Pair(1, 1))], Some(5));
+
+        let level = parse_input(TEST_INPUT_1, &CombatRules::standard()).unwrap().1;
+        let sweep = level.placement_sweep(ELF, stats, Ranking::Survivors);
+        assert_eq!(sweep.len(), level.occupants.iter().zip(&level.walls).filter(|&(o, &w)| o.is_none() && !w).count());
+        assert!(sweep.windows(2).all(|w| w[0].survivors >= w[1].survivors));
+
+        let best = sweep[0];
+        let mut check = level.clone();
+        check.add_unit(ELF, best.position, stats);
+        assert_eq!(check.fight(), best.outcome);
+
+        // Some losses here outscore every win, but the wins still come first.
+        let level = parse_input(MIXED_SWEEP, &CombatRules::standard()).unwrap().1;
+        let sweep = level.placement_sweep(ELF, stats, Ranking::Score);
+        let (wins, losses) = sweep.split_at(sweep.iter().take_while(|p| p.won()).count());
+        assert_eq!((wins.len(), losses.len()), (2, 4));
+        assert!(losses.iter().all(|p| !p.won()));
+        assert!(losses[0].outcome.score() > wins[0].outcome.score());
+        assert!(sweep.windows(2).all(|w| w[0].won() != w[1].won() || w[0].outcome.score() >= w[1].outcome.score()));
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(part1(TEST_INPUT_1), 27730);
@@ -374,6 +1090,15 @@ mod tests {
         assert_eq!(part1(TEST_INPUT_5), 18740);
     }
 
+    #[test]
+    fn test_search_elf_attack_power() {
+        assert_eq!(search_elf_attack_power(TEST_INPUT_1), Some(PowerSearch {
+            attack_power: 15,
+            outcome: Outcome { completed_rounds: 29, total_hp: 172 }
+        }));
+        assert_eq!(search_elf_attack_power(TEST_INPUT_5).map(|s| s.attack_power), Some(34));
+    }
+
     #[test]
     fn test_part2() {
         assert_eq!(part2(TEST_INPUT_1), 4988);
@@ -1,4 +1,9 @@
 use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use itertools::Itertools;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
@@ -8,135 +13,401 @@ use nom::IResult;
 use nom::multi::separated_list1;
 use nom::sequence::{preceded, separated_pair};
 use adventofcode2018::build_main;
+use adventofcode2018::image::Image;
+use adventofcode2018::rng::Rng;
+use crate::Cell::{Clay, Flowing, Sand, Settled, Spring};
 use crate::Line::{Horizontal, Vertical};
 
-struct Level {
-    data: Vec<Vec<char>>,
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Cell {
+    Sand,
+    Clay,
+    Settled,
+    Flowing,
+    Spring
+}
+
+impl Cell {
+    fn symbol(self) -> char {
+        match self {
+            Sand => '.',
+            Clay => '#',
+            Settled => '~',
+            Flowing => '|',
+            Spring => '+'
+        }
+    }
+
+    fn is_wet(self) -> bool {
+        matches!(self, Settled | Flowing)
+    }
+
+    /// Whether water can rest on top of the cell.
+    fn holds_water(self) -> bool {
+        matches!(self, Clay | Settled)
+    }
+}
+
+/// Storage for the ground, addressed by absolute coordinates.  Anything never set is sand.
+trait Ground {
+    fn new(x_min: usize, x_max: usize, y_top: usize, y_max: usize) -> Self;
+    fn get(&self, x: usize, y: usize) -> Cell;
+    fn set(&mut self, x: usize, y: usize, cell: Cell);
+    fn count(&self, y_min: usize, pred: fn(Cell) -> bool) -> usize;
+}
+
+/// Every cell of the bounding box, row by row.
+struct Dense {
+    cells: Vec<Cell>,
+    x_min: usize,
+    width: usize,
+    y_top: usize
+}
+
+impl Ground for Dense {
+    fn new(x_min: usize, x_max: usize, y_top: usize, y_max: usize) -> Dense {
+        let width = x_max - x_min + 1;
+        Dense { cells: vec![Sand; width * (y_max - y_top + 1)], x_min, width, y_top }
+    }
+
+    fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[(y - self.y_top) * self.width + x - self.x_min]
+    }
+
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        self.cells[(y - self.y_top) * self.width + x - self.x_min] = cell;
+    }
+
+    fn count(&self, y_min: usize, pred: fn(Cell) -> bool) -> usize {
+        self.cells[(y_min - self.y_top) * self.width..].iter()
+            .filter(|&&cell| pred(cell))
+            .count()
+    }
+}
+
+const CHUNK: usize = 16;
+
+/// The ground cut into `CHUNK`-square tiles that are only allocated once something other than
+/// sand is put in them, so memory follows the clay and water rather than the bounding box.
+struct Sparse {
+    chunks: Vec<Option<Box<[Cell; CHUNK * CHUNK]>>>,
+    x_min: usize,
+    y_top: usize,
+    width: usize,
+    height: usize,
+    chunks_wide: usize
+}
+
+impl Sparse {
+    fn locate(&self, x: usize, y: usize) -> (usize, usize) {
+        let (x, y) = (x - self.x_min, y - self.y_top);
+        ((y / CHUNK) * self.chunks_wide + x / CHUNK, (y % CHUNK) * CHUNK + x % CHUNK)
+    }
+
+    /// Cells in allocated chunks.
+    fn allocated(&self) -> usize {
+        self.chunks.iter().flatten().count() * CHUNK * CHUNK
+    }
+}
+
+impl Ground for Sparse {
+    fn new(x_min: usize, x_max: usize, y_top: usize, y_max: usize) -> Sparse {
+        let (width, height) = (x_max - x_min + 1, y_max - y_top + 1);
+        let chunks_wide = (width - 1) / CHUNK + 1;
+        let chunks_high = (height - 1) / CHUNK + 1;
+        Sparse { chunks: (0..chunks_wide * chunks_high).map(|_| None).collect(), x_min, y_top, width, height, chunks_wide }
+    }
+
+    fn get(&self, x: usize, y: usize) -> Cell {
+        let (chunk, i) = self.locate(x, y);
+        self.chunks[chunk].as_ref().map_or(Sand, |chunk| chunk[i])
+    }
+
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        let (chunk, i) = self.locate(x, y);
+        match &mut self.chunks[chunk] {
+            Some(chunk) => chunk[i] = cell,
+            None if cell == Sand => {},
+            empty => empty.insert(Box::new([Sand; CHUNK * CHUNK]))[i] = cell
+        }
+    }
+
+    /// Chunks are clipped to the bounding box, and unallocated ones count as all sand.
+    fn count(&self, y_min: usize, pred: fn(Cell) -> bool) -> usize {
+        self.chunks.iter().enumerate()
+            .map(|(n, chunk)| {
+                let (x0, y0) = (n % self.chunks_wide * CHUNK, n / self.chunks_wide * CHUNK);
+                let rows = y0.max(y_min - self.y_top)..(y0 + CHUNK).min(self.height);
+                let cols = x0..(x0 + CHUNK).min(self.width);
+                match chunk {
+                    None if pred(Sand) => rows.len() * cols.len(),
+                    None => 0,
+                    Some(chunk) => rows
+                        .flat_map(|y| cols.clone().map(move |x| chunk[(y % CHUNK) * CHUNK + x % CHUNK]))
+                        .filter(|&cell| pred(cell))
+                        .count()
+                }
+            })
+            .sum()
+    }
+}
+
+/// The ground spans columns `x_min..=x_max` and rows `y_top..=y_max`.  Only rows from `y_min`,
+/// the first with clay, are counted.  Sparse storage is the default since scans can be far wider
+/// than the clay in them; `Dense` is kept as the simple reference for the benchmark and tests.
+struct Level<G = Sparse> {
+    ground: G,
+    x_min: usize,
+    x_max: usize,
+    y_top: usize,
     y_min: usize,
+    y_max: usize,
     queue: VecDeque<(usize, usize)>
 }
 
-impl Level {
-    fn from_lines(lines: &[Line]) -> Level {
+/// The spring in the puzzle, used when the scan doesn't list any.
+const SPRING: (usize, usize) = (500, 0);
+
+impl<G: Ground> Level<G> {
+    /// Lay out the clay and springs, given as `(x, y)`.  The columns cover every clay vein and
+    /// spring with a column of sand either side.  Above the first clay row water can only fall
+    /// straight down and isn't counted, so springs higher up are lowered to the row just above
+    /// it and the rows in between are never stored.  Springs below the deepest clay are dropped
+    /// since their water leaves the scan at once.
+    fn new(lines: &[Line], springs: &[(usize, usize)]) -> Level<G> {
         let x_min = lines.iter()
             .map(|line| {
-                match line {
-                    &Horizontal(_, (x_min, _)) => x_min,
-                    &Vertical(_, x) => x
+                match *line {
+                    Horizontal(_, (x_min, _)) => x_min,
+                    Vertical(_, x) => x
                 }
             })
+            .chain(springs.iter().map(|&(x, _)| x))
             .min().unwrap() - 1;
 
         let x_max = lines.iter()
             .map(|line| {
-                match line {
-                    &Horizontal(_, (_, x_max)) => x_max,
-                    &Vertical(_, x) => x
+                match *line {
+                    Horizontal(_, (_, x_max)) => x_max,
+                    Vertical(_, x) => x
                 }
             })
+            .chain(springs.iter().map(|&(x, _)| x))
             .max().unwrap() + 1;
 
         let y_min = lines.iter()
             .map(|line| {
-                match line {
-                    &Horizontal(y, _) => y,
-                    &Vertical((y_min, _), _) => y_min
+                match *line {
+                    Horizontal(y, _) => y,
+                    Vertical((y_min, _), _) => y_min
                 }
             })
             .min().unwrap();
 
         let y_max = lines.iter()
             .map(|line| {
-                match line {
-                    &Horizontal(y, _) => y,
-                    &Vertical((_, y_max), _) => y_max
+                match *line {
+                    Horizontal(y, _) => y,
+                    Vertical((_, y_max), _) => y_max
                 }
             })
             .max().unwrap();
 
-        let mut data = (0..=y_max).map(|_| {
-            vec!['.'; x_max - x_min + 1]
-        }).collect_vec();
+        let y_top = y_min.saturating_sub(1);
+        let mut ground = G::new(x_min, x_max, y_top, y_max);
 
-        data[0][500-x_min] = '+';
+        let springs = springs.iter()
+            .filter(|&&(_, y)| y <= y_max)
+            .map(|&(x, y)| (y.max(y_top), x))
+            .unique()
+            .collect_vec();
+
+        springs.iter().for_each(|&(y, x)| ground.set(x, y, Spring));
 
         lines.iter().for_each(|line| {
-            match line {
-                &Horizontal(y, (x0, x1)) => {
-                    (x0..=x1).for_each(|x| data[y][x-x_min] = '#');
+            match *line {
+                Horizontal(y, (x0, x1)) => {
+                    (x0..=x1).for_each(|x| ground.set(x, y, Clay));
                 },
-                &Vertical((y0, y1), x) => {
-                    (y0..=y1).for_each(|y| data[y][x-x_min] = '#');
+                Vertical((y0, y1), x) => {
+                    (y0..=y1).for_each(|y| ground.set(x, y, Clay));
                 }
             }
         });
 
-        let mut queue = VecDeque::new();
-        queue.push_front((0, 500-x_min));
+        let queue = springs.into_iter()
+            .filter(|&(y, x)| ground.get(x, y) == Spring)
+            .collect();
+
+        Level { ground, x_min, x_max, y_top, y_min, y_max, queue }
+    }
 
-        Level { data, y_min, queue }
+    fn from_scan(scan: &Scan) -> Level<G> {
+        if scan.springs.is_empty() { Level::new(&scan.lines, &[SPRING]) } else { Level::new(&scan.lines, &scan.springs) }
     }
 
     fn run(&mut self) {
+        self.run_with(|_, _, _| {});
+    }
+
+    /// Run, calling `on_step` with the level, the number of queue operations so far and the
+    /// `(x, y)` cell just worked on.
+    fn run_with(&mut self, mut on_step: impl FnMut(&Self, usize, (usize, usize))) {
+        let mut steps = 0;
         while let Some((y, x)) = self.queue.pop_front() {
-            match self.data[y][x] {
-                '+' | '|' => {
-                    if y + 1 == self.data.len() {
-                        continue;
-                    }
-
-                    match self.data[y+1][x] {
-                        '.' => {
-                            let mut y0 = y;
-                            while y0 + 1 < self.data.len() && !"~#".contains(self.data[y0+1][x]) {
-                                y0 += 1;
-                            }
+            self.pour(y, x);
+            steps += 1;
+            on_step(self, steps, (x, y));
+        }
+    }
 
-                            (y+1..=y0).for_each(|y1| self.data[y1][x] = '|');
-                            self.queue.push_back((y0, x));
-                        },
-                        '#' | '~' => {
-                            let mut l = x;
-                            while l > 0 && self.data[y][l-1] != '#'
-                                && "#~".contains(self.data[y+1][l]) {
-                                l -= 1;
-                            }
+    /// Run, handing over a frame every `every` queue operations.
+    fn run_animated(&mut self, every: usize, width: usize, height: usize, mut emit: impl FnMut(Frame)) {
+        assert!(every > 0, "frames must be at least one step apart");
+        self.run_with(|level, steps, (x, y)| {
+            if steps % every == 0 {
+                emit(level.frame(steps, (x, y), width, height));
+            }
+        });
+    }
 
-                            let mut r = x;
-                            while r + 1 < self.data[y].len() && self.data[y][r+1] != '#'
-                                &&  "#~".contains(self.data[y+1][r]) {
-                                r += 1;
-                            }
+    fn pour(&mut self, y: usize, x: usize) {
+        match self.ground.get(x, y) {
+            Spring | Flowing => {
+                if y == self.y_max {
+                    return;
+                }
 
-                            let left_is_wall = l > 0 && self.data[y][l-1] == '#';
-                            let right_is_wall = r + 1 < self.data[y].len()
-                                && self.data[y][r+1] == '#';
+                match self.ground.get(x, y + 1) {
+                    Sand | Spring => {
+                        let mut y0 = y;
+                        while y0 < self.y_max && !self.ground.get(x, y0 + 1).holds_water() {
+                            y0 += 1;
+                        }
 
-                            if left_is_wall && right_is_wall {
-                                (l..=r).for_each(|x| {
-                                    self.data[y][x] = '~';
-                                    self.queue.push_back((y - 1, x));
-                                });
+                        (y+1..=y0).for_each(|y1| self.ground.set(x, y1, Flowing));
+                        self.queue.push_back((y0, x));
+                    },
+                    Clay | Settled => {
+                        let mut l = x;
+                        while l > self.x_min && self.ground.get(l - 1, y) != Clay
+                            && self.ground.get(l, y + 1).holds_water() {
+                            l -= 1;
+                        }
+
+                        let mut r = x;
+                        while r < self.x_max && self.ground.get(r + 1, y) != Clay
+                            && self.ground.get(r, y + 1).holds_water() {
+                            r += 1;
+                        }
+
+                        let left_is_wall = l > self.x_min && self.ground.get(l - 1, y) == Clay;
+                        let right_is_wall = r < self.x_max && self.ground.get(r + 1, y) == Clay;
+
+                        if left_is_wall && right_is_wall {
+                            (l..=r).for_each(|x| {
+                                self.ground.set(x, y, Settled);
+                                self.queue.push_back((y - 1, x));
+                            });
+                        }
+                        else {
+                            (l..=r).for_each(|x| self.ground.set(x, y, Flowing));
+                            if !left_is_wall {
+                                self.queue.push_back((y, l));
                             }
-                            else {
-                                (l..=r).for_each(|x| self.data[y][x] = '|');
-                                if !left_is_wall {
-                                    self.queue.push_back((y, l));
-                                }
-                                if !right_is_wall {
-                                    self.queue.push_back((y, r));
-                                }
+                            if !right_is_wall {
+                                self.queue.push_back((y, r));
                             }
-                        },
-                        _ => continue
-                    }
-                },
-                _ => continue
-            };
-            // self.print();
-            // println!();
+                        }
+                    },
+                    Flowing => {}
+                }
+            },
+            _ => {}
         }
     }
+
+    /// Tiles from the first clay row down that satisfy `pred`.
+    fn count(&self, pred: fn(Cell) -> bool) -> usize {
+        self.ground.count(self.y_min, pred)
+    }
+
+    /// Rows `y_top..=y_max` as cells.
+    fn grid(&self) -> Vec<Vec<Cell>> {
+        (self.y_top..=self.y_max)
+            .map(|y| (self.x_min..=self.x_max).map(|x| self.ground.get(x, y)).collect())
+            .collect()
+    }
+
+    fn image(&self, scale: usize) -> Image {
+        Image::from_grid(&self.grid(), scale, |&cell| colour(cell))
+    }
+
+    /// A `width` by `height` window centred on `active` as far as the ground allows.
+    fn frame(&self, step: usize, active: (usize, usize), width: usize, height: usize) -> Frame {
+        let window = |min: usize, max: usize, centre: usize, size: usize| {
+            let size = size.min(max - min + 1);
+            let start = centre.saturating_sub(size / 2).clamp(min, max + 1 - size);
+            start..start + size
+        };
+        let xs = window(self.x_min, self.x_max, active.0, width);
+        let ys = window(self.y_top, self.y_max, active.1, height);
+
+        let cells = ys.clone()
+            .map(|y| xs.clone().map(|x| self.ground.get(x, y)).collect())
+            .collect();
+
+        Frame { step, active, origin: (xs.start, ys.start), cells }
+    }
+}
+
+/// Part of the ground partway through a run.
+struct Frame {
+    step: usize,
+    active: (usize, usize),
+    /// Coordinates of the top left cell.
+    origin: (usize, usize),
+    cells: Vec<Vec<Cell>>
+}
+
+impl Frame {
+    fn image(&self, scale: usize) -> Image {
+        Image::from_grid(&self.cells, scale, |&cell| colour(cell))
+    }
+}
+
+/// The window with the active cell marked `@`, under a line giving the step and position.
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (x0, y0) = self.origin;
+        writeln!(f, "step {} at x={}, y={}", self.step, self.active.0, self.active.1)?;
+        for (dy, row) in self.cells.iter().enumerate() {
+            let line = row.iter().enumerate()
+                .map(|(dx, cell)| if (x0 + dx, y0 + dy) == self.active { '@' } else { cell.symbol() })
+                .collect::<String>();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<G: Ground> Display for Level<G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for row in self.grid() {
+            writeln!(f, "{}", row.into_iter().map(Cell::symbol).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+fn colour(cell: Cell) -> [u8; 3] {
+    match cell {
+        Clay => [120, 72, 32],
+        Settled => [16, 64, 192],
+        Flowing => [128, 192, 255],
+        Spring => [224, 32, 32],
+        Sand => [240, 224, 176]
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -145,7 +416,18 @@ enum Line {
     Vertical((usize, usize), usize)
 }
 
-fn parse_input(input: &str) -> IResult<&str, Level> {
+enum Entry {
+    Clay(Line),
+    Spring(usize, usize)
+}
+
+struct Scan {
+    lines: Vec<Line>,
+    springs: Vec<(usize, usize)>
+}
+
+/// Clay veins, plus any number of `spring at x=500, y=0` lines.
+fn parse_scan(input: &str) -> IResult<&str, Scan> {
     fn number(input: &str) -> IResult<&str, usize> {
         map_res(digit1, |s: &str| s.parse::<usize>())(input)
     }
@@ -172,33 +454,129 @@ fn parse_input(input: &str) -> IResult<&str, Level> {
         )(input)
     }
 
+    fn spring(input: &str) -> IResult<&str, Entry> {
+        map(
+            preceded(
+                tag("spring at "),
+                separated_pair(preceded(tag("x="), number), tag(", "), preceded(tag("y="), number))
+            ),
+            |(x, y)| Entry::Spring(x, y)
+        )(input)
+    }
+
     map(
-        separated_list1(newline, alt((horizontal, vertical))),
-        |lines| Level::from_lines(&lines)
+        separated_list1(newline, alt((map(alt((horizontal, vertical)), Entry::Clay), spring))),
+        |entries| {
+            let mut lines = Vec::new();
+            let mut springs = Vec::new();
+            for entry in entries {
+                match entry {
+                    Entry::Clay(line) => lines.push(line),
+                    Entry::Spring(x, y) => springs.push((x, y))
+                }
+            }
+            Scan { lines, springs }
+        }
     )(input)
 }
 
+fn parse_input(input: &str) -> IResult<&str, Level> {
+    map(parse_scan, |scan| Level::from_scan(&scan))(input)
+}
+
 fn part1(input: &str) -> usize {
     let mut level = parse_input(input).unwrap().1;
     level.run();
-
-    level.data[level.y_min..].iter()
-        .flat_map(|row| row)
-        .filter(|&&c| c == '|' || c == '~')
-        .count()
+    level.count(Cell::is_wet)
 }
 
 fn part2(input: &str) -> usize {
     let mut level = parse_input(input).unwrap().1;
     level.run();
+    level.count(|cell| cell == Settled)
+}
+
+/// Write the settled state to the path given, as PNG if it ends in `.png` and PPM otherwise,
+/// with each tile drawn `scale` pixels square.
+fn export_image(input: &str, args: &[String]) {
+    let path = args.first().expect("usage: --image <path> [scale]");
+    let scale = args.get(1).map_or(1, |scale| scale.parse().expect("the scale should be a number"));
+
+    let mut level = parse_input(input).unwrap().1;
+    level.run();
+    level.image(scale).save(path).unwrap();
+}
+
+/// Write a frame every so many queue operations to the directory given, numbered in order:
+/// `--frames <dir> [every] [txt|ppm] [width] [height]`.
+fn dump_frames(input: &str, args: &[String]) {
+    let dir = Path::new(args.first().expect("usage: --frames <dir> [every] [txt|ppm] [width] [height]"));
+    let number = |i: usize, default: usize| args.get(i).map_or(default, |n| n.parse().expect("expected a number"));
+    let every = number(1, 10);
+    let format = args.get(2).map_or("txt", |format| format.as_str());
+    let (width, height) = (number(3, 80), number(4, 40));
+
+    fs::create_dir_all(dir).unwrap();
+    let mut level = parse_input(input).unwrap().1;
+    let mut count = 0;
+    level.run_animated(every, width, height, |frame| {
+        let path = dir.join(format!("frame_{count:06}.{format}"));
+        match format {
+            "ppm" => frame.image(4).save(path).unwrap(),
+            _ => fs::write(path, frame.to_string()).unwrap()
+        }
+        count += 1;
+    });
+    println!("Wrote {count} frames to {}", dir.display());
+}
+
+/// A random scan about `width` columns wide and `depth` rows deep: `basins` open-topped basins
+/// of assorted sizes, and a spring every five hundred columns along the top.
+fn generate_scan(seed: u64, width: usize, depth: usize, basins: usize) -> String {
+    let mut rng = Rng::new(seed);
+    let mut lines = Vec::new();
+
+    for _ in 0..basins {
+        let x = 1000 + rng.below(width);
+        let y = 2 + rng.below(depth);
+        let w = 2 + rng.below(30);
+        let h = 1 + rng.below(15);
+        lines.push(format!("x={x}, y={y}..{}", y + h));
+        lines.push(format!("x={}, y={y}..{}", x + w, y + h));
+        lines.push(format!("y={}, x={x}..{}", y + h, x + w));
+    }
+    for x in (1000..1000 + width).step_by(500) {
+        lines.push(format!("spring at x={x}, y=0"));
+    }
+
+    lines.join("\n")
+}
+
+fn time<G: Ground>(scan: &Scan) -> (Level<G>, u128) {
+    let start = Instant::now();
+    let mut level = Level::from_scan(scan);
+    level.run();
+    (level, start.elapsed().as_millis())
+}
+
+/// Time both storage layouts on generated scans of growing size, checking they agree.
+fn bench(_input: &str, _args: &[String]) {
+    for (width, depth, basins) in [(2_000, 2_000, 100), (20_000, 2_000, 1_000), (200_000, 2_000, 10_000)] {
+        let scan = parse_scan(&generate_scan(17, width, depth, basins)).unwrap().1;
+        let (dense, dense_ms) = time::<Dense>(&scan);
+        let (sparse, sparse_ms) = time::<Sparse>(&scan);
+
+        assert_eq!(dense.count(Cell::is_wet), sparse.count(Cell::is_wet));
+        assert_eq!(dense.count(|cell| cell == Settled), sparse.count(|cell| cell == Settled));
 
-    level.data[level.y_min..].iter()
-        .flat_map(|row| row)
-        .filter(|&&c| c == '~')
-        .count()
+        println!(
+            "{width}x{depth}, {basins} basins: dense {dense_ms} ms over {} cells, sparse {sparse_ms} ms over {} cells",
+            dense.ground.cells.len(), sparse.ground.allocated()
+        );
+    }
 }
 
-build_main!("day17.txt", "Part 1" => part1, "Part 2" => part2);
+build_main!("day17.txt", "Part 1" => part1, "Part 2" => part2; "--image" => export_image, "--bench" => bench, "--frames" => dump_frames);
 
 #[cfg(test)]
 mod tests {
@@ -213,6 +591,118 @@ x=498, y=10..13
 x=504, y=10..13
 y=13, x=498..504";
 
+    #[test]
+    fn test_display() {
+        let mut level = parse_input(TEST_INPUT).unwrap().1;
+        level.run();
+        assert_eq!(level.to_string(), "\
+......+.......
+......|.....#.
+.#..#||||...#.
+.#..#~~#|.....
+.#..#~~#|.....
+.#~~~~~#|.....
+.#~~~~~#|.....
+.#######|.....
+........|.....
+...|||||||||..
+...|#~~~~~#|..
+...|#~~~~~#|..
+...|#~~~~~#|..
+...|#######|..
+");
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut level = parse_input(TEST_INPUT).unwrap().1;
+        let mut frames = Vec::new();
+        level.run_animated(5, 7, 4, |frame| frames.push(frame));
+
+        let mut steps = 0;
+        parse_input(TEST_INPUT).unwrap().1.run_with(|_, step, _| steps = step);
+
+        assert_eq!(frames.len(), steps / 5);
+        assert!(frames.iter().all(|frame| frame.cells.len() == 4 && frame.cells.iter().all(|row| row.len() == 7)));
+        assert_eq!(frames[2].to_string(), "\
+step 15 at x=499, y=2
+....+..
+....|..
+..#@|..
+..#~~#.
+");
+        // Windows stop at the edge of the ground rather than running off it.
+        assert_eq!(frames[6].origin, (501, 7));
+
+        let zero_interval = std::panic::catch_unwind(|| parse_input(TEST_INPUT).unwrap().1.run_animated(0, 7, 4, drop));
+        assert!(zero_interval.is_err());
+    }
+
+    #[test]
+    fn test_image() {
+        let mut level = parse_input(TEST_INPUT).unwrap().1;
+        level.run();
+        let image = level.image(2);
+
+        assert_eq!((image.width, image.height), (2 * 14, 2 * 14));
+        let pixel = |x: usize, y: usize| image.pixels[2 * y * image.width + 2 * (x - 494)];
+        assert_eq!(pixel(500, 0), colour(Spring));
+        assert_eq!(pixel(495, 2), colour(Clay));
+        assert_eq!(pixel(500, 6), colour(Settled));
+        assert_eq!(pixel(500, 1), colour(Flowing));
+        assert_eq!(pixel(494, 0), colour(Sand));
+    }
+
+    #[test]
+    fn test_springs() {
+        // Far off to the side, a second spring just adds a column of falling water.
+        let input = format!("{TEST_INPUT}\nspring at x=500, y=0\nspring at x=520, y=0");
+        let mut level = parse_input(&input).unwrap().1;
+        level.run();
+        assert_eq!(level.grid()[0].len(), 521 - 494 + 1);
+        assert_eq!(level.count(Cell::is_wet), 57 + 13);
+
+        // Springs that feed the same basins give the same result whatever order they start in.
+        let lines = parse_scan(TEST_INPUT).unwrap().1.lines;
+        let mut forward: Level = Level::new(&lines, &[(500, 0), (503, 5), (490, 0)]);
+        let mut backward: Level = Level::new(&lines, &[(490, 0), (503, 5), (500, 0)]);
+        forward.run();
+        backward.run();
+        assert_eq!(forward.grid(), backward.grid());
+        assert_eq!(forward.grid()[0][0], Sand);
+        assert_eq!(forward.grid()[5][503 - 489], Spring);
+        // The extra springs add a column at x=490 and a short fall at x=503 into the main flow.
+        assert_eq!((forward.count(Cell::is_wet), forward.count(|cell| cell == Settled)), (57 + 13 + 3, 29));
+    }
+
+    #[test]
+    fn test_sparse() {
+        for seed in 0..20 {
+            let scan = parse_scan(&generate_scan(seed, 300, 200, 40)).unwrap().1;
+            let mut dense: Level<Dense> = Level::from_scan(&scan);
+            let mut sparse: Level<Sparse> = Level::from_scan(&scan);
+            dense.run();
+            sparse.run();
+
+            assert_eq!(dense.grid(), sparse.grid(), "seed {seed}");
+            assert_eq!(dense.count(Cell::is_wet), sparse.count(Cell::is_wet));
+            assert_eq!(dense.count(|cell| cell == Sand), sparse.count(|cell| cell == Sand), "seed {seed}");
+            assert_eq!(dense.count(|cell| cell == Clay), sparse.count(|cell| cell == Clay));
+            assert!(sparse.ground.allocated() < dense.ground.cells.len());
+        }
+
+        // Springs far above the clay are lowered to just above it.
+        let lines = parse_scan(TEST_INPUT).unwrap().1.lines.into_iter()
+            .map(|line| match line {
+                Horizontal(y, xs) => Horizontal(y + 1000, xs),
+                Vertical((y0, y1), x) => Vertical((y0 + 1000, y1 + 1000), x)
+            })
+            .collect_vec();
+        let mut level: Level<Sparse> = Level::new(&lines, &[(500, 0)]);
+        level.run();
+        assert_eq!((level.y_top, level.count(Cell::is_wet)), (1000, 57));
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(part1(TEST_INPUT), 57);
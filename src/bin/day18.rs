@@ -1,43 +1,46 @@
-use std::collections::HashMap;
-use std::ops::Add;
+use std::time::Instant;
 use itertools::Itertools;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::newline;
-use nom::combinator::{map, value};
+use nom::combinator::value;
 use nom::IResult;
 use nom::multi::{many1, separated_list1};
+use adventofcode2018::automaton::{Automaton, Edge, Grid, Neighbourhood};
 use adventofcode2018::build_main;
+use adventofcode2018::cycle::find_cycle;
 
-#[derive(Copy, Clone)]
-struct Pair(i8, i8);
-impl Add for Pair {
-    type Output = Pair;
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Space { Empty, Tree, LumberYard }
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Pair(self.0 + rhs.0, self.1 + rhs.1)
+fn evolve(space: Space, neighbors: &[Space]) -> Space {
+    let count = |kind| neighbors.iter().filter(|&&s| s == kind).count();
+    match space {
+        Space::Empty => if count(Space::Tree) >= 3 { Space::Tree } else { Space::Empty },
+        Space::Tree => if count(Space::LumberYard) >= 3 { Space::LumberYard } else { Space::Tree },
+        Space::LumberYard => {
+            if count(Space::LumberYard) >= 1 && count(Space::Tree) >= 1 { Space::LumberYard } else { Space::Empty }
+        }
     }
 }
 
-const DIRECTIONS: [Pair; 8] = [
-    Pair(-1, -1), Pair(-1, 0), Pair(-1, 1),
-    Pair(0, -1), Pair(0, 1),
-    Pair(1, -1), Pair(1, 0), Pair(1, 1)
-];
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
-enum Space { Empty, Tree, LumberYard }
+type Rule = fn(Space, &[Space]) -> Space;
 
-#[derive(Clone, Eq, PartialEq, Hash)]
-struct Level {
-    data: Vec<Vec<Space>>
+/// The forest as a general-purpose grid of spaces, kept as a reference for [`Level`].
+#[derive(Clone)]
+struct GridLevel {
+    grid: Grid<Space, Rule>
 }
 
-impl Level {
+impl GridLevel {
+    fn new(data: Vec<Vec<Space>>) -> GridLevel {
+        GridLevel { grid: Grid::new(data, Neighbourhood::Moore(1), Edge::Omit, evolve as Rule) }
+    }
+
     fn score(&self) -> usize {
         let mut trees = 0;
         let mut lumberyards = 0;
-        self.data.iter().flatten().for_each(|&space| {
+        self.grid.cells().iter().for_each(|&space| {
             match space {
                 Space::Tree => trees += 1,
                 Space::LumberYard => lumberyards += 1,
@@ -48,68 +51,165 @@ impl Level {
         trees * lumberyards
     }
 
-    fn get(&self,  Pair(i, j): Pair) -> Option<Space> {
-        if i < 0 || j < 0 {
-            return None;
+    fn key(&self) -> Vec<Space> {
+        self.grid.cells().to_vec()
+    }
+}
+
+impl Automaton for GridLevel {
+    fn step(&mut self) {
+        self.grid.step();
+    }
+
+    fn generation(&self) -> usize {
+        self.grid.generation()
+    }
+}
+
+/// Neighbour counts for 64 cells at once, one bit of each count per plane.  Counts of four or
+/// more all look the same, which is as far as the rules need to tell them apart.
+#[derive(Default)]
+struct Count {
+    ones: u64,
+    twos: u64,
+    many: u64
+}
+
+impl Count {
+    fn add(&mut self, cells: u64) {
+        let carry = self.ones & cells;
+        self.ones ^= cells;
+        self.many |= self.twos & carry;
+        self.twos ^= carry;
+    }
+
+    fn at_least_one(&self) -> u64 {
+        self.ones | self.twos | self.many
+    }
+
+    fn at_least_three(&self) -> u64 {
+        self.many | (self.twos & self.ones)
+    }
+}
+
+/// The forest packed into two bitplanes, one bit per acre: a tree, a lumberyard, or neither for
+/// open ground.  Each row takes a whole number of words, so a generation is a few word
+/// operations per 64 acres, written into a second pair of planes that is then swapped in.
+#[derive(Clone)]
+struct Level {
+    trees: Vec<u64>,
+    yards: Vec<u64>,
+    next_trees: Vec<u64>,
+    next_yards: Vec<u64>,
+    width: usize,
+    /// Words per row.
+    words: usize,
+    generation: usize
+}
+
+impl Level {
+    fn new(data: &[Vec<Space>]) -> Level {
+        let width = data.first().map_or(0, |row| row.len());
+        let words = width.div_ceil(64);
+        let mut level = Level {
+            trees: vec![0; words * data.len()],
+            yards: vec![0; words * data.len()],
+            next_trees: vec![0; words * data.len()],
+            next_yards: vec![0; words * data.len()],
+            width,
+            words,
+            generation: 0
+        };
+
+        for (i, row) in data.iter().enumerate() {
+            for (j, &space) in row.iter().enumerate() {
+                let (word, bit) = (i * words + j / 64, 1 << (j % 64));
+                match space {
+                    Space::Tree => level.trees[word] |= bit,
+                    Space::LumberYard => level.yards[word] |= bit,
+                    Space::Empty => ()
+                }
+            }
         }
 
-        Some(*self.data.get(i as usize)?.get(j as usize)?)
-    }
-
-    fn neighbors(&self, loc: Pair) -> Vec<Space> {
-        DIRECTIONS.iter()
-            .filter_map(|&dir| self.get(loc + dir))
-            .collect_vec()
-    }
-
-    fn round(&mut self) {
-        self.data = self.data.iter().enumerate()
-            .map(|(i, row)| {
-                row.iter().enumerate()
-                    .map(|(j, &space)| {
-                        let neighbors = self.neighbors(Pair(i as i8, j as i8));
-                        match space {
-                            Space::Empty => {
-                                let trees = neighbors.into_iter()
-                                    .filter(|s| *s == Space::Tree)
-                                    .count();
-
-                                if trees >= 3 { Space::Tree } else { Space::Empty }
-                            },
-                            Space::Tree => {
-                                let lumberyards = neighbors.into_iter()
-                                    .filter(|s| *s == Space::LumberYard)
-                                    .count();
-
-                                if lumberyards >= 3 { Space::LumberYard } else { Space::Tree }
-                            },
-                            Space::LumberYard => {
-                                let lumberyards = neighbors.iter()
-                                    .filter(|s| **s == Space::LumberYard)
-                                    .count();
-                                let trees = neighbors.into_iter()
-                                    .filter(|s| *s == Space::Tree)
-                                    .count();
-
-                                if lumberyards >= 1 && trees >= 1 { Space::LumberYard } else { Space::Empty }
-                            }
-                        }
-                    }).collect()
-            }).collect()
-    }
-}
-
-impl Iterator for Level {
-    type Item = Level;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let result = Some(self.clone());
-        self.round();
-        result
+        level
+    }
+
+    fn score(&self) -> usize {
+        let count = |plane: &[u64]| plane.iter().map(|word| word.count_ones() as usize).sum::<usize>();
+        count(&self.trees) * count(&self.yards)
+    }
+
+    fn get(&self, row: usize, col: usize) -> Space {
+        let (word, bit) = (row * self.words + col / 64, 1 << (col % 64));
+        match (self.trees[word] & bit != 0, self.yards[word] & bit != 0) {
+            (true, _) => Space::Tree,
+            (_, true) => Space::LumberYard,
+            _ => Space::Empty
+        }
+    }
+
+    /// Both planes, which is all that tells two generations apart.
+    fn key(&self) -> Vec<u64> {
+        [self.trees.as_slice(), &self.yards].concat()
+    }
+
+    /// The bits of word `i` of a row that lie inside the forest.
+    fn mask(&self, i: usize) -> u64 {
+        match self.width - 64 * i {
+            64.. => !0,
+            bits => (1 << bits) - 1
+        }
+    }
+}
+
+/// Add the neighbours in one row of a plane to the counts for word `i`: the cells to the west
+/// and east, and the cells themselves unless this is the row being counted for.
+fn add_row(count: &mut Count, row: &[u64], i: usize, centre: bool) {
+    let before = if i > 0 { row[i - 1] >> 63 } else { 0 };
+    let after = row.get(i + 1).map_or(0, |word| word << 63);
+
+    count.add((row[i] << 1) | before);
+    count.add((row[i] >> 1) | after);
+    if !centre {
+        count.add(row[i]);
+    }
+}
+
+impl Automaton for Level {
+    fn step(&mut self) {
+        let rows = self.trees.len() / self.words.max(1);
+
+        for row in 0..rows {
+            for i in 0..self.words {
+                let (mut near_trees, mut near_yards) = (Count::default(), Count::default());
+                for other in row.saturating_sub(1)..(row + 2).min(rows) {
+                    let span = other * self.words..(other + 1) * self.words;
+                    add_row(&mut near_trees, &self.trees[span.clone()], i, other == row);
+                    add_row(&mut near_yards, &self.yards[span], i, other == row);
+                }
+
+                let word = row * self.words + i;
+                let (tree, yard) = (self.trees[word], self.yards[word]);
+                let open = !(tree | yard);
+
+                self.next_trees[word] = ((open & near_trees.at_least_three()) | (tree & !near_yards.at_least_three())) & self.mask(i);
+                self.next_yards[word] = (tree & near_yards.at_least_three())
+                    | (yard & near_yards.at_least_one() & near_trees.at_least_one());
+            }
+        }
+
+        std::mem::swap(&mut self.trees, &mut self.next_trees);
+        std::mem::swap(&mut self.yards, &mut self.next_yards);
+        self.generation += 1;
+    }
+
+    fn generation(&self) -> usize {
+        self.generation
     }
 }
 
-fn parse_input(input: &str) -> IResult<&str, Level> {
+fn parse_input(input: &str) -> IResult<&str, Vec<Vec<Space>>> {
     fn space(input: &str) -> IResult<&str, Space> {
         alt((
             value(Space::Empty, tag(".")),
@@ -118,44 +218,84 @@ fn parse_input(input: &str) -> IResult<&str, Level> {
         ))(input)
     }
 
-    map(
-        separated_list1(newline, many1(space)),
-        |data| Level { data }
-    )(input)
+    separated_list1(newline, many1(space))(input)
+}
+
+/// Score after a billion minutes, skipping ahead once the forest starts repeating itself.  The
+/// cycle is found on the keys alone, stepping a single copy of the level in place.
+fn long_run<L, K>(mut level: L, key: fn(&L) -> K, score: fn(&L) -> usize) -> usize
+where
+    L: Automaton + Clone,
+    K: Clone + PartialEq
+{
+    let mut stepped = level.clone();
+    let keys = std::iter::from_fn(move || {
+        let result = Some(key(&stepped));
+        stepped.step();
+        result
+    });
+
+    let cycle = find_cycle(keys, K::clone).unwrap();
+    (0..cycle.reduce(1000000000)).for_each(|_| level.step());
+    score(&level)
 }
 
 fn part1(input: &str) -> usize {
-    let mut level = parse_input(input).unwrap().1;
-    level.nth(10).unwrap().score()
+    let level = Level::new(&parse_input(input).unwrap().1);
+    level.generations().nth(10).unwrap().score()
 }
 
 fn part2(input: &str) -> usize {
-    let level = parse_input(input).unwrap().1;
-
-    let mut seen = HashMap::new();
-    let mut cycle_start = 0;
-    let mut cycle_len = 0;
-    let mut scores = Vec::new();
-
-    for (i, lvl) in level.enumerate() {
-        if seen.contains_key(&lvl) {
-            cycle_start = seen[&lvl];
-            cycle_len = i - cycle_start;
-            break;
-        }
-        else {
-            scores.push(lvl.score());
-            seen.insert(lvl, i);
-        }
-    }
+    long_run(Level::new(&parse_input(input).unwrap().1), Level::key, Level::score)
+}
+
+/// Whether both representations hold the same space everywhere.
+fn same_forest(grid: &GridLevel, level: &Level) -> bool {
+    (0..grid.grid.rows())
+        .cartesian_product(0..grid.grid.cols())
+        .all(|(row, col)| grid.grid.get(row, col) == level.get(row, col))
+}
+
+/// The forest repeated `times` times in each direction.
+fn tile(data: &[Vec<Space>], times: usize) -> Vec<Vec<Space>> {
+    let rows = data.iter().map(|row| row.repeat(times)).collect::<Vec<_>>();
+    rows.iter().cycle().take(times * rows.len()).cloned().collect()
+}
+
+/// Time both representations on part 2 and on a thousand generations of ever larger copies of
+/// the forest, checking they agree.
+fn bench(input: &str, _args: &[String]) {
+    let data = parse_input(input).unwrap().1;
 
-    let target = 1000000000;
-    let i = (target - cycle_start) % cycle_len;
+    let start = Instant::now();
+    let grid = long_run(GridLevel::new(data.clone()), GridLevel::key, GridLevel::score);
+    let grid_ms = start.elapsed().as_millis();
+    let start = Instant::now();
+    let packed = long_run(Level::new(&data), Level::key, Level::score);
+    let packed_ms = start.elapsed().as_millis();
 
-    scores[cycle_start + i]
+    assert_eq!(grid, packed);
+    println!("part 2: grid {grid_ms} ms, packed {packed_ms} ms");
+
+    for times in [2, 4] {
+        let tiled = tile(&data, times);
+
+        let start = Instant::now();
+        let grid = GridLevel::new(tiled.clone()).generations().nth(1000).unwrap();
+        let grid_ms = start.elapsed().as_millis();
+        let start = Instant::now();
+        let packed = Level::new(&tiled).generations().nth(1000).unwrap();
+        let packed_ms = start.elapsed().as_millis();
+
+        assert!(same_forest(&grid, &packed));
+        println!(
+            "{}x{}, 1000 generations: grid {grid_ms} ms, packed {packed_ms} ms",
+            tiled[0].len(), tiled.len()
+        );
+    }
 }
 
-build_main!("day18.txt", "Part 1" => part1, "Part 2" => part2);
+build_main!("day18.txt", "Part 1" => part1, "Part 2" => part2; "--bench" => bench);
 
 #[cfg(test)]
 mod tests {
@@ -176,4 +316,17 @@ mod tests {
     fn test_part1() {
         assert_eq!(part1(TEST_INPUT), 1147);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_packed() {
+        // Wide enough that rows span several words, with a partial one at the end.
+        let data = tile(&parse_input(TEST_INPUT).unwrap().1, 7);
+        let grids = GridLevel::new(data.clone()).generations();
+        let levels = Level::new(&data).generations();
+
+        for (grid, level) in grids.zip(levels).take(50) {
+            assert!(same_forest(&grid, &level), "generation {}", level.generation());
+            assert_eq!(grid.score(), level.score());
+        }
+    }
+}
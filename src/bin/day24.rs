@@ -1,17 +1,39 @@
-use crate::GroupRef::{ImmuneSystem, Infection};
-use crate::Modifiers::{Immunities, Weaknesses};
 use adventofcode2018::build_main;
-use itertools::{chain, Itertools};
+use adventofcode2018::parallel::parallel_map;
+use itertools::Itertools;
 use nom::branch::alt;
-use nom::bytes::complete::tag;
+use nom::bytes::complete::{tag, take_till1};
 use nom::character::complete::{alpha1, char, digit1, multispace1, newline, space1};
-use nom::combinator::{all_consuming, map, map_res, opt};
+use nom::combinator::{all_consuming, map, map_opt, map_res, opt, verify};
 use nom::multi::separated_list1;
-use nom::sequence::{delimited, pair, preceded, separated_pair, tuple};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
 use nom::IResult;
 use std::cmp::{min, Reverse};
+use std::fmt;
+use std::fmt::Write;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::Modifiers::{Immunities, Weaknesses};
+
+/// `s` as a quoted JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str(r#"\""#),
+            '\\' => out.push_str(r"\\"),
+            '\n' => out.push_str(r"\n"),
+            '\r' => out.push_str(r"\r"),
+            '\t' => out.push_str(r"\t"),
+            c if c < ' ' => write!(out, r"\u{:04x}", c as u32).unwrap(),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 struct Group {
     units: usize,
     hit_points: usize,
@@ -35,132 +57,313 @@ impl Group {
         else if enemy.immunities.contains(&self.attack_type) { 0 }
         else { base_damage }
     }
+
+    fn to_json(&self) -> String {
+        let strings = |v: &[String]| v.iter().map(|s| json_string(s)).join(",");
+        format!(
+            r#"{{"units":{},"hit_points":{},"attack_damage":{},"attack_type":{},"initiative":{},"weaknesses":[{}],"immunities":[{}]}}"#,
+            self.units, self.hit_points, self.attack_damage, json_string(&self.attack_type), self.initiative,
+            strings(&self.weaknesses), strings(&self.immunities)
+        )
+    }
 }
 
-fn find_choices(allies: &[Group], enemies: &[Group]) -> Vec<Option<usize>> {
-    let mut chosen = enemies.iter().map(|_| false).collect_vec();
+/// The group in input form.  Immunities come before weaknesses, each in the order parsed.
+impl Display for Group {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} units each with {} hit points", self.units, self.hit_points)?;
 
-    let mut order = (0..allies.len())
-        .filter(|&i| allies[i].units > 0)
-        .collect_vec();
+        let modifiers = [("immune to", &self.immunities), ("weak to", &self.weaknesses)].into_iter()
+            .filter(|(_, types)| !types.is_empty())
+            .map(|(kind, types)| format!("{kind} {}", types.join(", ")))
+            .collect_vec();
+        if !modifiers.is_empty() {
+            write!(f, " ({})", modifiers.join("; "))?;
+        }
 
-    order.sort_by_key(|&i| Reverse((allies[i].effective_damage(), allies[i].initiative)));
+        write!(
+            f, " with an attack that does {} {} damage at initiative {}",
+            self.attack_damage, self.attack_type, self.initiative
+        )
+    }
+}
 
-    let mut choices = allies.iter().map(|_| None).collect_vec();
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct GroupRef {
+    army: usize,
+    group: usize
+}
 
-    for i in order {
-        let attacker = &allies[i];
-        let mut choice = None;
-        let mut best_stats = (0, 0, 0);
+/// Damage an attacker would deal to a defender it considered during target selection.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Selection {
+    attacker: GroupRef,
+    defender: GroupRef,
+    damage: usize
+}
 
-        for j in 0..enemies.len() {
-            let defender = &enemies[j];
-            if defender.units == 0
-                || chosen[j]
-                || defender.immunities.contains(&attacker.attack_type) {
-                continue
-            }
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Attack {
+    attacker: GroupRef,
+    defender: GroupRef,
+    damage: usize,
+    units_killed: usize
+}
 
-            let damage = attacker.damage_to(defender);
-            let effective_damage = defender.effective_damage();
-            let initiative = defender.initiative;
-            let stats = (damage, effective_damage, initiative);
+/// Everything that happened in one round, as laid out in the puzzle text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct RoundReport {
+    names: Vec<String>,
+    /// Units in each group of each army at the start of the round.
+    units: Vec<Vec<usize>>,
+    /// Army by army from the last one back, as the puzzle text lists them, and in selection
+    /// order within each army.
+    selections: Vec<Selection>,
+    attacks: Vec<Attack>
+}
 
-            if stats > best_stats {
-                choice = Some(j);
-                best_stats = stats;
-            }
+fn write_armies(f: &mut impl fmt::Write, names: &[String], units: &[Vec<usize>]) -> fmt::Result {
+    for (name, army) in names.iter().zip(units) {
+        writeln!(f, "{name}:")?;
+        if army.iter().all(|&units| units == 0) {
+            writeln!(f, "No groups remain.")?;
         }
-
-        if let Some(j) = choice {
-            chosen[j] = true;
-            choices[i] = Some(j);
+        for (i, &units) in army.iter().enumerate().filter(|&(_, &units)| units > 0) {
+            writeln!(f, "Group {} contains {units} units", i + 1)?;
         }
     }
-
-    choices
+    Ok(())
 }
 
-#[derive(Debug)]
-enum GroupRef {
-    Infection(usize),
-    ImmuneSystem(usize)
+impl Display for RoundReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = |r: GroupRef| format!("{} group {}", self.names[r.army], r.group + 1);
+        // With only two armies the defender's is implied.
+        let defender = |r: GroupRef| if self.names.len() > 2 { name(r) } else { format!("group {}", r.group + 1) };
+
+        write_armies(f, &self.names, &self.units)?;
+        writeln!(f)?;
+        for s in &self.selections {
+            writeln!(f, "{} would deal defending {} {} damage", name(s.attacker), defender(s.defender), s.damage)?;
+        }
+        writeln!(f)?;
+        for a in &self.attacks {
+            writeln!(f, "{} attacks defending {}, killing {} units", name(a.attacker), defender(a.defender), a.units_killed)?;
+        }
+        Ok(())
+    }
 }
 
 struct TurnStats {
     units_killed: usize,
-    immune_system_units_remaining: usize,
-    infection_units_remaining: usize
+    units_remaining: Vec<usize>,
+    /// Whether any group left may still target another.
+    hostilities_remain: bool
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Army {
+    name: String,
+    groups: Vec<Group>
+}
+
+/// Any number of armies.  `may_target[a][b]` says whether groups of army `a` may attack groups
+/// of army `b`; by default every army may attack every other one.
+#[derive(Debug, Clone, Eq, PartialEq)]
 struct War {
-    immune_system: Vec<Group>,
-    infection: Vec<Group>
+    armies: Vec<Army>,
+    may_target: Vec<Vec<bool>>
 }
 
 impl War {
-    fn apply_boost(&mut self, boost: usize) {
-        self.immune_system.iter_mut()
+    fn new(armies: Vec<Army>) -> War {
+        let may_target = (0..armies.len())
+            .map(|a| (0..armies.len()).map(|b| a != b).collect())
+            .collect();
+        War { armies, may_target }
+    }
+
+    fn army(&self, name: &str) -> Option<usize> {
+        self.armies.iter().position(|army| army.name == name)
+    }
+
+    /// Stop armies `a` and `b` from attacking each other.
+    fn ally(&mut self, a: usize, b: usize) {
+        self.may_target[a][b] = false;
+        self.may_target[b][a] = false;
+    }
+
+    fn apply_boost(&mut self, army: usize, boost: usize) {
+        self.armies[army].groups.iter_mut()
             .for_each(|g| g.attack_damage += boost);
     }
 
-    fn get(&self, gref: &GroupRef) -> &Group {
-        match gref {
-            &Infection(i) => &self.infection[i],
-            &ImmuneSystem(i) => &self.immune_system[i]
-        }
+    fn get(&self, gref: GroupRef) -> &Group {
+        &self.armies[gref.army].groups[gref.group]
     }
 
-    fn get_mut(&mut self, gref: &GroupRef) -> &mut Group {
-        match gref {
-            &Infection(i) => &mut self.infection[i],
-            &ImmuneSystem(i) => &mut self.immune_system[i]
+    fn get_mut(&mut self, gref: GroupRef) -> &mut Group {
+        &mut self.armies[gref.army].groups[gref.group]
+    }
+
+    fn groups(&self) -> impl Iterator<Item=GroupRef> + '_ {
+        self.armies.iter().enumerate()
+            .flat_map(|(army, a)| (0..a.groups.len()).map(move |group| GroupRef { army, group }))
+    }
+
+    fn units(&self) -> Vec<Vec<usize>> {
+        self.armies.iter()
+            .map(|army| army.groups.iter().map(|g| g.units).collect())
+            .collect()
+    }
+
+    fn units_remaining(&self) -> Vec<usize> {
+        self.armies.iter()
+            .map(|army| army.groups.iter().map(|g| g.units).sum())
+            .collect()
+    }
+
+    fn hostilities_remain(&self) -> bool {
+        let alive = self.units_remaining().iter().positions(|&units| units > 0).collect_vec();
+        alive.iter().cartesian_product(&alive).any(|(&a, &b)| self.may_target[a][b])
+    }
+
+    /// Pick a target for every group, strongest first.  Each pairing weighed along the way is
+    /// pushed onto `considered`, in selection order.
+    fn choose_targets(&self, mut considered: Option<&mut Vec<Selection>>) -> Vec<Vec<Option<GroupRef>>> {
+        let mut chosen = self.armies.iter().map(|a| vec![false; a.groups.len()]).collect_vec();
+        let mut choices = self.armies.iter().map(|a| vec![None; a.groups.len()]).collect_vec();
+
+        let mut order = self.groups()
+            .filter(|&r| self.get(r).units > 0)
+            .collect_vec();
+
+        order.sort_by_key(|&r| Reverse((self.get(r).effective_damage(), self.get(r).initiative)));
+
+        for attacker_ref in order {
+            let attacker = self.get(attacker_ref);
+            let mut choice = None;
+            let mut best_stats = (0, 0, 0);
+
+            for defender_ref in self.groups().filter(|d| self.may_target[attacker_ref.army][d.army]) {
+                let defender = self.get(defender_ref);
+                if defender.units == 0
+                    || chosen[defender_ref.army][defender_ref.group]
+                    || defender.immunities.contains(&attacker.attack_type) {
+                    continue
+                }
+
+                let damage = attacker.damage_to(defender);
+                if let Some(considered) = considered.as_mut() {
+                    considered.push(Selection { attacker: attacker_ref, defender: defender_ref, damage });
+                }
+
+                let effective_damage = defender.effective_damage();
+                let initiative = defender.initiative;
+                let stats = (damage, effective_damage, initiative);
+
+                if stats > best_stats {
+                    choice = Some(defender_ref);
+                    best_stats = stats;
+                }
+            }
+
+            if let Some(d) = choice {
+                chosen[d.army][d.group] = true;
+                choices[attacker_ref.army][attacker_ref.group] = Some(d);
+            }
         }
+
+        choices
     }
 
     fn advance(&mut self) -> TurnStats {
+        self.advance_with(None)
+    }
+
+    fn advance_reporting(&mut self) -> (TurnStats, RoundReport) {
+        let mut report = RoundReport {
+            names: self.armies.iter().map(|army| army.name.clone()).collect(),
+            units: self.units(),
+            selections: Vec::new(),
+            attacks: Vec::new()
+        };
+        let stats = self.advance_with(Some(&mut report));
+        report.selections.sort_by_key(|s| Reverse(s.attacker.army));
+        (stats, report)
+    }
+
+    fn advance_with(&mut self, mut report: Option<&mut RoundReport>) -> TurnStats {
         let mut units_killed = 0;
 
-        let infection_choices = find_choices(&self.infection, &self.immune_system);
-        let immune_system_choices = find_choices(&self.immune_system, &self.infection);
+        let choices = self.choose_targets(report.as_mut().map(|report| &mut report.selections));
 
-        let mut turn_order =
-            chain!(
-                (0..self.infection.len()).map(|i| Infection(i)),
-                (0..self.immune_system.len()).map(|j| ImmuneSystem(j))
-            )
-            .filter(|r| self.get(r).units > 0)
+        let mut turn_order = self.groups()
+            .filter(|&r| self.get(r).units > 0)
             .collect_vec();
 
-        turn_order.sort_by_key(|r| Reverse(self.get(r).initiative));
+        turn_order.sort_by_key(|&r| Reverse(self.get(r).initiative));
 
         for r in turn_order {
-            let attacker = self.get(&r);
-
-            let choice = match r {
-                Infection(i) => infection_choices[i].map(ImmuneSystem),
-                ImmuneSystem(i) => immune_system_choices[i].map(Infection)
-            };
+            let attacker = self.get(r);
 
-            if let Some(defref) = choice {
-                let defender = self.get(&defref);
+            if let Some(defref) = choices[r.army][r.group].filter(|_| attacker.units > 0) {
+                let defender = self.get(defref);
                 let damage = attacker.damage_to(defender);
                 let num_dead = min(defender.units, damage / defender.hit_points);
                 units_killed += num_dead;
-                self.get_mut(&defref).units -= num_dead;
+                self.get_mut(defref).units -= num_dead;
+
+                if let Some(report) = report.as_mut() {
+                    report.attacks.push(Attack { attacker: r, defender: defref, damage, units_killed: num_dead });
+                }
             }
         }
 
-        let immune_system_units_remaining = self.immune_system.iter()
-            .map(|g| g.units)
-            .sum::<usize>();
+        TurnStats {
+            units_killed,
+            units_remaining: self.units_remaining(),
+            hostilities_remain: self.hostilities_remain()
+        }
+    }
 
-        let infection_units_remaining = self.infection.iter()
-            .map(|g| g.units)
-            .sum::<usize>();
+    fn to_json(&self) -> String {
+        let armies = self.armies.iter()
+            .map(|army| format!(
+                r#"{{"name":{},"groups":[{}]}}"#,
+                json_string(&army.name), army.groups.iter().map(|g| g.to_json()).join(",")
+            ))
+            .join(",");
+        let may_target = self.may_target.iter()
+            .map(|targets| format!("[{}]", targets.iter().positions(|&t| t).join(",")))
+            .join(",");
+        format!(r#"{{"armies":[{armies}],"may_target":[{may_target}]}}"#)
+    }
+}
 
-        TurnStats { units_killed, immune_system_units_remaining, infection_units_remaining }
+/// The war in input form.  Alliances are only listed when some armies may not attack each other:
+/// mutual ones as `A, B` and one-sided ones as `A spares B`.
+impl Display for War {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let armies = self.armies.iter()
+            .map(|army| format!("{}:\n{}", army.name, army.groups.iter().join("\n")))
+            .join("\n\n");
+        write!(f, "{armies}")?;
+
+        let mut alliances = Vec::new();
+        for (a, b) in (0..self.armies.len()).tuple_combinations() {
+            let (name_a, name_b) = (&self.armies[a].name, &self.armies[b].name);
+            match (self.may_target[a][b], self.may_target[b][a]) {
+                (false, false) => alliances.push(format!("{name_a}, {name_b}")),
+                (false, true) => alliances.push(format!("{name_a} spares {name_b}")),
+                (true, false) => alliances.push(format!("{name_b} spares {name_a}")),
+                (true, true) => {}
+            }
+        }
+        if !alliances.is_empty() {
+            write!(f, "\n\nAlliances:\n{}", alliances.join("\n"))?;
+        }
+        Ok(())
     }
 }
 
@@ -188,9 +391,7 @@ impl Iterator for WarIter {
 
         let stats = self.war.advance();
 
-        if stats.units_killed == 0
-            || stats.immune_system_units_remaining == 0
-            || stats.infection_units_remaining == 0 {
+        if stats.units_killed == 0 || !stats.hostilities_remain {
             self.is_done = true;
         }
 
@@ -198,11 +399,212 @@ impl Iterator for WarIter {
     }
 }
 
+/// How a war ended.  `rounds` counts rounds in which at least one unit died.
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Outcome {
+    /// Only groups of `armies` are left, and none of those armies may attack another.
+    Won { armies: Vec<usize>, rounds: usize, survivors: usize },
+    /// Groups that may attack each other are left, but none can kill anything any more.
+    Stalemate { rounds: usize, units: Vec<usize> }
+}
+
+impl Outcome {
+    fn is_won_by(&self, army: usize) -> bool {
+        matches!(self, Outcome::Won { armies, .. } if armies.contains(&army))
+    }
+
+    fn survivors(&self) -> usize {
+        match self {
+            Outcome::Won { survivors, .. } => *survivors,
+            Outcome::Stalemate { units, .. } => units.iter().sum()
+        }
+    }
+}
+
+fn plural(word: &str, count: usize) -> String {
+    if count == 1 { word.to_owned() } else { format!("{word}s") }
+}
+
+impl Outcome {
+    fn describe(&self, names: &[String]) -> String {
+        match self {
+            Outcome::Won { armies, rounds, survivors } => {
+                let winners = armies.iter().map(|&army| &names[army]).join(" and ");
+                let verb = if armies.len() == 1 { "wins" } else { "win" };
+                format!("{winners} {verb} with {survivors} units after {rounds} {}", plural("round", *rounds))
+            },
+            Outcome::Stalemate { rounds, units } => {
+                format!("stalemate after {rounds} {} with {} units", plural("round", *rounds), units.iter().join("/"))
+            }
+        }
+    }
+}
+
+impl War {
+    fn fight(self) -> Outcome {
+        for (rounds, stats) in self.into_iter().enumerate() {
+            let rounds = if stats.units_killed > 0 { rounds + 1 } else { rounds };
+
+            if !stats.hostilities_remain {
+                return Outcome::Won {
+                    armies: stats.units_remaining.iter().positions(|&units| units > 0).collect(),
+                    rounds,
+                    survivors: stats.units_remaining.iter().sum()
+                };
+            }
+            if stats.units_killed == 0 {
+                return Outcome::Stalemate { rounds, units: stats.units_remaining };
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+const MAX_BOOST: usize = 1 << 32;
+
+fn outcome_with_boost(war: &War, army: usize, boost: usize) -> Outcome {
+    let mut war = war.clone();
+    war.apply_boost(army, boost);
+    war.fight()
+}
+
+/// The smallest boost with which `army` wins, and how that war ends.
+///
+/// Winning is not monotonic in the boost: a stalemate or a loss can sit between two wins.  So
+/// doubling only looks for some winning boost, and then every boost below it is fought out.
+/// Threads take boosts in increasing order and skip them once someone has won with a lower
+/// boost.  Gives up past `max_boost`.
+fn minimal_boost(war: &War, army: usize, max_boost: usize) -> Option<(usize, Outcome)> {
+    let mut high = 0;
+    let mut outcome = outcome_with_boost(war, army, high);
+    while !outcome.is_won_by(army) {
+        if high >= max_boost {
+            return None;
+        }
+        high = high.saturating_mul(2).clamp(1, max_boost);
+        outcome = outcome_with_boost(war, army, high);
+    }
+
+    let best = AtomicUsize::new(high);
+    let boosts = (0..high).collect_vec();
+
+    let lower = parallel_map(&boosts, |&boost| {
+        if boost > best.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let outcome = outcome_with_boost(war, army, boost);
+        outcome.is_won_by(army).then(|| {
+            best.fetch_min(boost, Ordering::Relaxed);
+            (boost, outcome)
+        })
+    });
+    lower.into_iter().flatten().next().or(Some((high, outcome)))
+}
+
+/// A tweak to a single group.  Percentages are signed and round towards zero.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Change {
+    Remove,
+    Units(isize),
+    Damage(isize),
+    Initiative(isize)
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Remove => write!(f, "removed"),
+            Change::Units(percent) => write!(f, "units {percent:+}%"),
+            Change::Damage(percent) => write!(f, "damage {percent:+}%"),
+            Change::Initiative(percent) => write!(f, "initiative {percent:+}%")
+        }
+    }
+}
+
+impl War {
+    fn perturbed(&self, gref: GroupRef, change: Change) -> War {
+        fn scale(value: usize, percent: isize) -> usize {
+            (value as isize * (100 + percent) / 100).max(0) as usize
+        }
+
+        let mut war = self.clone();
+        let group = war.get_mut(gref);
+        match change {
+            Change::Remove => group.units = 0,
+            Change::Units(percent) => group.units = scale(group.units, percent),
+            Change::Damage(percent) => group.attack_damage = scale(group.attack_damage, percent),
+            Change::Initiative(percent) => group.initiative = scale(group.initiative, percent)
+        }
+        war
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Sensitivity {
+    group: GroupRef,
+    change: Change,
+    outcome: Outcome,
+    minimal_boost: Option<usize>
+}
+
+/// Remove every group in turn, or scale its units, damage or initiative up and down by
+/// `percent`, and fight each of those wars both unboosted and with the smallest boost that lets
+/// `army` win.  The wars are shared out between threads; results come back group by group.
+fn sensitivity(war: &War, army: usize, percent: isize, max_boost: usize) -> Vec<Sensitivity> {
+    let changes = [
+        Change::Remove,
+        Change::Units(percent), Change::Units(-percent),
+        Change::Damage(percent), Change::Damage(-percent),
+        Change::Initiative(percent), Change::Initiative(-percent)
+    ];
+    let jobs = war.groups()
+        .cartesian_product(changes)
+        .collect_vec();
+
+    parallel_map(&jobs, |&(group, change)| {
+        let war = war.perturbed(group, change);
+        let minimal_boost = minimal_boost(&war, army, max_boost).map(|(boost, _)| boost);
+        let outcome = war.fight();
+        Sensitivity { group, change, outcome, minimal_boost }
+    })
+}
+
+/// One line per change, next to the unchanged war's outcome and minimal boost.
+fn sensitivity_report(war: &War, army: usize, percent: isize, max_boost: usize) -> String {
+    let names = war.armies.iter().map(|army| army.name.clone()).collect_vec();
+    let baseline = war.clone().fight();
+    let baseline_boost = minimal_boost(war, army, max_boost).map(|(boost, _)| boost);
+    let boost = |boost: Option<usize>| boost.map_or("none".to_owned(), |boost| boost.to_string());
+
+    let mut out = String::new();
+    writeln!(
+        out, "Unchanged: {}; minimal boost {}",
+        baseline.describe(&names), boost(baseline_boost)
+    ).unwrap();
+
+    for s in sensitivity(war, army, percent, max_boost) {
+        let outcome = if s.outcome == baseline { "no change".to_owned() } else { s.outcome.describe(&names) };
+        let boost_change = match (s.minimal_boost, baseline_boost) {
+            (Some(new), Some(old)) if new != old => format!(" ({:+})", new as isize - old as isize),
+            _ => String::new()
+        };
+        writeln!(
+            out, "{} group {} {}: {outcome}; minimal boost {}{boost_change}",
+            names[s.group.army], s.group.group + 1, s.change, boost(s.minimal_boost)
+        ).unwrap();
+    }
+    out
+}
+
 enum Modifiers {
     Immunities(Vec<String>),
     Weaknesses(Vec<String>)
 }
 
+/// Armies are introduced by their name and a colon.  An optional `Alliances:` section at the end
+/// lists armies that may not attack each other, either `A, B, C` or one-sided as `A spares B`.
 fn parse_input(input: &str) -> IResult<&str, War> {
     fn number(input: &str) -> IResult<&str, usize> {
         map_res(digit1, |s: &str| s.parse::<usize>())(input)
@@ -256,42 +658,103 @@ fn parse_input(input: &str) -> IResult<&str, War> {
         )(input)
     }
 
-    all_consuming(
+    fn army(input: &str) -> IResult<&str, Army> {
         map(
             separated_pair(
-                preceded(pair(tag("Immune System:"), newline), separated_list1(newline, group)),
-                multispace1,
-                preceded(pair(tag("Infection:"), newline), separated_list1(newline, group))
+                verify(take_till1(|c| c == ':' || c == '\n'), |name: &str| name != "Alliances"),
+                pair(char(':'), newline),
+                separated_list1(newline, group)
             ),
-            |(immune_system, infection)| War { immune_system, infection }
+            |(name, groups)| Army { name: name.to_owned(), groups }
+        )(input)
+    }
+
+    fn alliances(input: &str) -> IResult<&str, Vec<&str>> {
+        preceded(
+            terminated(tag("Alliances:"), newline),
+            separated_list1(newline, take_till1(|c| c == '\n'))
+        )(input)
+    }
+
+    all_consuming(
+        map_opt(
+            pair(separated_list1(multispace1, army), opt(preceded(multispace1, alliances))),
+            |(armies, alliances)| {
+                let mut war = War::new(armies);
+                for line in alliances.unwrap_or_default() {
+                    if let Some((a, b)) = line.split_once(" spares ") {
+                        let (a, b) = (war.army(a)?, war.army(b)?);
+                        war.may_target[a][b] = false;
+                    }
+                    else {
+                        let allies = line.split(", ").map(|name| war.army(name)).collect::<Option<Vec<_>>>()?;
+                        for (&a, &b) in allies.iter().tuple_combinations() {
+                            war.ally(a, b);
+                        }
+                    }
+                }
+                Some(war)
+            }
         )
     )(input)
 }
 
-fn part1(input: &str) -> usize {
+/// The whole battle round by round, ending with the surviving groups.
+fn battle_report(mut war: War) -> String {
+    let mut out = String::new();
+
+    loop {
+        let (stats, report) = war.advance_reporting();
+        writeln!(out, "{report}").unwrap();
+
+        if stats.units_killed == 0 || !stats.hostilities_remain {
+            break;
+        }
+    }
+
+    let names = war.armies.iter().map(|army| army.name.clone()).collect_vec();
+    write_armies(&mut out, &names, &war.units()).unwrap();
+    out
+}
+
+/// The war from the input, with the immune system boosted by the optional first argument.
+fn boosted_war(input: &str, args: &[String]) -> War {
+    let mut war = parse_input(input).unwrap().1;
+    if let Some(boost) = args.first() {
+        let army = war.army("Immune System").expect("only the immune system can be boosted");
+        war.apply_boost(army, boost.parse().expect("the boost should be a number"));
+    }
+    war
+}
+
+fn report(input: &str, args: &[String]) {
+    print!("{}", battle_report(boosted_war(input, args)));
+}
+
+fn json(input: &str, args: &[String]) {
+    println!("{}", boosted_war(input, args).to_json());
+}
+
+fn sensitivity_analysis(input: &str, args: &[String]) {
     let war = parse_input(input).unwrap().1;
-    let it = war.into_iter();
+    let army = war.army("Immune System").expect("the immune system is the army being boosted");
+    let percent = args.first().map_or(10, |percent| percent.parse().expect("the percentage should be a number"));
+    print!("{}", sensitivity_report(&war, army, percent, MAX_BOOST));
+}
 
-    it.last().map(|s| s.infection_units_remaining + s.immune_system_units_remaining).unwrap()
+fn part1(input: &str) -> usize {
+    parse_input(input).unwrap().1.fight().survivors()
 }
 
 fn part2(input: &str) -> usize {
-    let base_war = parse_input(input).unwrap().1;
-
-    for boost in 0.. {
-        let mut war = base_war.clone();
-        war.apply_boost(boost);
-        let it = war.into_iter();
-        let result = it.last().unwrap();
-        if result.infection_units_remaining == 0 {
-            return result.immune_system_units_remaining;
-        }
-    }
+    let war = parse_input(input).unwrap().1;
+    let immune_system = war.army("Immune System").unwrap();
 
-    unreachable!()
+    let (_, outcome) = minimal_boost(&war, immune_system, MAX_BOOST).unwrap();
+    outcome.survivors()
 }
 
-build_main!("day24.txt", "Part 1" => part1, "Part 2" => part2);
+build_main!("day24.txt", "Part 1" => part1, "Part 2" => part2; "--report" => report, "--json" => json, "--sensitivity" => sensitivity_analysis);
 
 #[cfg(test)]
 mod tests {
@@ -309,4 +772,157 @@ Infection:
     fn test_part1() {
         assert_eq!(part1(TEST_INPUT), 5216);
     }
+
+    #[test]
+    fn test_round_trip() {
+        let war = parse_input(TEST_INPUT).unwrap().1;
+        assert_eq!(war.to_string(), TEST_INPUT);
+
+        let reordered = "Immune System:
+1 units each with 2 hit points (weak to fire, cold; immune to slashing) with an attack that does 3 fire damage at initiative 1
+
+Infection:
+4 units each with 5 hit points with an attack that does 6 cold damage at initiative 2";
+        let war = parse_input(reordered).unwrap().1;
+        assert_eq!(war.armies[0].groups[0].to_string(), "1 units each with 2 hit points (immune to slashing; weak to fire, cold) with an attack that does 3 fire damage at initiative 1");
+        assert_eq!(parse_input(&war.to_string()).unwrap().1, war);
+
+        assert_eq!(war.to_json(), concat!(
+            r#"{"armies":[{"name":"Immune System","groups":[{"units":1,"hit_points":2,"attack_damage":3,"attack_type":"fire","initiative":1,"weaknesses":["fire","cold"],"immunities":["slashing"]}]},"#,
+            r#"{"name":"Infection","groups":[{"units":4,"hit_points":5,"attack_damage":6,"attack_type":"cold","initiative":2,"weaknesses":[],"immunities":[]}]}],"#,
+            r#""may_target":[[1],[0]]}"#
+        ));
+
+        let quoted = reordered.replace("Infection", "The \"Bad\" Guys\\\t");
+        let war = parse_input(&quoted).unwrap().1;
+        assert!(war.to_json().contains(r#"{"name":"The \"Bad\" Guys\\\t","groups""#));
+    }
+
+    #[test]
+    fn test_battle_report() {
+        let war = parse_input(TEST_INPUT).unwrap().1;
+        let report = battle_report(war);
+        let rounds = report.split("\n\n").collect_vec();
+
+        assert_eq!(rounds[0], "Immune System:
+Group 1 contains 17 units
+Group 2 contains 989 units
+Infection:
+Group 1 contains 801 units
+Group 2 contains 4485 units");
+        assert_eq!(rounds[1], "Infection group 1 would deal defending group 1 185832 damage
+Infection group 1 would deal defending group 2 185832 damage
+Infection group 2 would deal defending group 2 107640 damage
+Immune System group 1 would deal defending group 1 76619 damage
+Immune System group 1 would deal defending group 2 153238 damage
+Immune System group 2 would deal defending group 1 24725 damage");
+        assert_eq!(rounds[2], "Infection group 2 attacks defending group 2, killing 84 units
+Immune System group 2 attacks defending group 1, killing 4 units
+Immune System group 1 attacks defending group 2, killing 51 units
+Infection group 1 attacks defending group 1, killing 17 units");
+        assert!(report.ends_with("Immune System:
+No groups remain.
+Infection:
+Group 1 contains 782 units
+Group 2 contains 4434 units
+"));
+
+        let mut war = parse_input(TEST_INPUT).unwrap().1;
+        let (_, report) = war.advance_reporting();
+        assert_eq!(report.attacks[0], Attack {
+            attacker: GroupRef { army: 1, group: 1 },
+            defender: GroupRef { army: 0, group: 1 },
+            damage: 107640,
+            units_killed: 84
+        });
+    }
+
+    #[test]
+    fn test_outcome_with_boost() {
+        let war = parse_input(TEST_INPUT).unwrap().1;
+        assert_eq!(outcome_with_boost(&war, 0, 0), Outcome::Won { armies: vec![1], rounds: 8, survivors: 5216 });
+        assert!(matches!(outcome_with_boost(&war, 0, 1570), Outcome::Won { armies, survivors: 51, .. } if armies == [0]));
+
+        let stalemate = "Immune System:
+10 units each with 10 hit points (immune to cold) with an attack that does 10 fire damage at initiative 2
+
+Infection:
+10 units each with 10 hit points (immune to fire) with an attack that does 10 cold damage at initiative 1";
+        let war = parse_input(stalemate).unwrap().1;
+        assert_eq!(
+            outcome_with_boost(&war, 0, 5),
+            Outcome::Stalemate { rounds: 0, units: vec![10, 10] }
+        );
+        assert_eq!(minimal_boost(&war, 0, 1000), None);
+    }
+
+    #[test]
+    fn test_minimal_boost() {
+        let war = parse_input(TEST_INPUT).unwrap().1;
+        let (boost, outcome) = minimal_boost(&war, 0, 1 << 32).unwrap();
+        assert_eq!(boost, 1570);
+        assert!(outcome.is_won_by(0));
+        assert_eq!(outcome.survivors(), 51);
+
+        // Boosts of 3 and 5 or more win, but 4 loses.
+        let input = "Immune System:
+12 units each with 20 hit points (immune to cold) with an attack that does 3 fire damage at initiative 1
+3 units each with 20 hit points (immune to fire) with an attack that does 21 fire damage at initiative 3
+
+Infection:
+2 units each with 12 hit points (immune to cold) with an attack that does 17 cold damage at initiative 4
+10 units each with 26 hit points (immune to cold) with an attack that does 7 slashing damage at initiative 2";
+        let war = parse_input(input).unwrap().1;
+        assert!(outcome_with_boost(&war, 0, 4).is_won_by(1));
+        assert_eq!(minimal_boost(&war, 0, 1000), Some((3, Outcome::Won { armies: vec![0], rounds: 8, survivors: 6 })));
+        assert_eq!(minimal_boost(&war, 0, 3).map(|(boost, _)| boost), Some(3));
+        assert_eq!(minimal_boost(&war, 0, 2), None);
+        assert_eq!(minimal_boost(&war, 0, 0), None);
+    }
+
+    #[test]
+    fn test_alliances() {
+        let input = format!("{TEST_INPUT}
+
+Reindeer:
+200 units each with 500 hit points (weak to slashing) with an attack that does 300 cold damage at initiative 5
+
+Alliances:
+Immune System, Reindeer");
+        let war = parse_input(&input).unwrap().1;
+        assert_eq!(war.to_string(), input);
+        assert_eq!(war.may_target, [[false, true, false], [true, false, true], [false, true, false]]);
+        assert_eq!(war.clone().fight(), Outcome::Won { armies: vec![1], rounds: 9, survivors: 5206 });
+        assert_eq!(
+            minimal_boost(&war, 0, 1 << 32),
+            Some((1536, Outcome::Won { armies: vec![0], rounds: 54, survivors: 20 }))
+        );
+
+        let report = battle_report(war.clone());
+        assert!(report.contains("Reindeer group 1 attacks defending Infection group 1, killing 12 units"));
+
+        let mut war = war;
+        war.may_target[2][0] = true;
+        assert!(war.to_string().ends_with("Alliances:\nImmune System spares Reindeer"));
+        assert_eq!(parse_input(&war.to_string()).unwrap().1, war);
+    }
+
+    #[test]
+    fn test_sensitivity() {
+        let war = parse_input(TEST_INPUT).unwrap().1;
+        let report = sensitivity_report(&war, 0, 10, MAX_BOOST);
+        let lines = report.lines().collect_vec();
+
+        assert_eq!(lines.len(), 1 + 4 * 7);
+        assert_eq!(lines[0], "Unchanged: Infection wins with 5216 units after 8 rounds; minimal boost 1570");
+        assert_eq!(lines[8], "Immune System group 2 removed: Infection wins with 5235 units after 1 round; minimal boost none");
+        assert_eq!(lines[9], "Immune System group 2 units +10%: Infection wins with 5212 units after 9 rounds; minimal boost 1304 (-266)");
+        assert_eq!(lines[22], "Infection group 2 removed: Infection wins with 767 units after 9 rounds; minimal boost 460 (-1110)");
+        assert_eq!(lines[26], "Infection group 2 damage -10%: no change; minimal boost 1371 (-199)");
+    }
+
+    #[test]
+    fn test_part2() {
+        assert_eq!(part2(TEST_INPUT), 51);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+/// Where an eventually periodic sequence of states starts repeating, and how often.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cycle {
+    /// Index of the first state inside the cycle.
+    pub start: usize,
+    pub length: usize
+}
+
+impl Cycle {
+    /// The earliest step with the same state as step `n`.
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.start { n } else { self.start + (n - self.start) % self.length }
+    }
+}
+
+/// Find the cycle in a sequence of states with Brent's algorithm, comparing states by `key`.
+/// Only two keys are held at a time, and the sequence is replayed from a clone of `states` to
+/// find where the cycle starts.  Returns `None` if the sequence ends first.
+pub fn find_cycle<I, K, F>(states: I, mut key: F) -> Option<Cycle>
+where
+    I: Iterator + Clone,
+    K: Clone + PartialEq,
+    F: FnMut(&I::Item) -> K
+{
+    let mut hare = states.clone();
+    let mut tortoise = key(&hare.next()?);
+    let mut hare_key = key(&hare.next()?);
+    let mut power = 1;
+    let mut length = 1;
+
+    while tortoise != hare_key {
+        if power == length {
+            tortoise = hare_key.clone();
+            power *= 2;
+            length = 0;
+        }
+        hare_key = key(&hare.next()?);
+        length += 1;
+    }
+
+    let mut tortoise = states.clone();
+    let mut hare = states.skip(length);
+    let mut start = 0;
+    while key(&tortoise.next()?) != key(&hare.next()?) {
+        start += 1;
+    }
+
+    Some(Cycle { start, length })
+}
+
+/// A numeric sequence whose differences eventually repeat: from `start` on, every `period` steps
+/// the value changes by `step`.  This covers sequences that settle into a cycle (a `step` of
+/// zero) as well as ones that grow linearly.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pattern {
+    pub start: usize,
+    pub period: usize,
+    pub step: isize,
+    /// Values up to the end of the first period.
+    prefix: Vec<isize>
+}
+
+impl Pattern {
+    /// Look for the shortest period, and then the earliest start, that holds up to the end of
+    /// `values` and has been seen to repeat at least `repeats` times.
+    pub fn find(values: &[isize], repeats: usize) -> Option<Pattern> {
+        let repeats = repeats.max(1);
+
+        (1..=values.len() / (repeats + 1)).find_map(|period| {
+            let step = values[values.len() - 1] - values[values.len() - 1 - period];
+            let start = (0..values.len() - period).rev()
+                .find(|&i| values[i + period] - values[i] != step)
+                .map_or(0, |i| i + 1);
+
+            (values.len() - start >= (repeats + 1) * period).then(|| Pattern {
+                start,
+                period,
+                step,
+                prefix: values[..start + period].to_vec()
+            })
+        })
+    }
+
+    /// The value at any step, however far out.
+    pub fn value_at(&self, n: usize) -> isize {
+        if n < self.prefix.len() {
+            return self.prefix[n];
+        }
+
+        let periods = (n - self.start) / self.period;
+        self.prefix[self.start + (n - self.start) % self.period] + periods as isize * self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_cycle() {
+        // 3, 10, 101, 2, 5, 26, 167, 95, 101, 2, ...
+        let states = std::iter::successors(Some(3u32), |&x| Some((x * x + 1) % 255));
+        let cycle = find_cycle(states.clone(), |&x| x).unwrap();
+        assert_eq!(cycle, Cycle { start: 2, length: 6 });
+
+        let step = |n| states.clone().nth(n).unwrap();
+        assert_eq!(step(cycle.reduce(1_000_000)), step(1_000_000));
+        assert_eq!(cycle.reduce(5), 5);
+
+        // The key decides what counts as the same state.
+        assert_eq!(find_cycle(0.., |&x| x % 7), Some(Cycle { start: 0, length: 7 }));
+        assert_eq!(find_cycle(0..100, |&x| x), None);
+    }
+
+    #[test]
+    fn test_pattern() {
+        let linear = [5, 1, 8, 10, 12, 14, 16, 18, 20, 22];
+        let pattern = Pattern::find(&linear, 5).unwrap();
+        assert_eq!((pattern.start, pattern.period, pattern.step), (2, 1, 2));
+        assert_eq!(pattern.value_at(1), 1);
+        assert_eq!(pattern.value_at(9), 22);
+        assert_eq!(pattern.value_at(1_000_000_000_000_000), 2_000_000_000_000_004);
+
+        let periodic = [9, 1, 2, 3, 1, 2, 3, 1, 2, 3, 1];
+        let pattern = Pattern::find(&periodic, 2).unwrap();
+        assert_eq!((pattern.start, pattern.period, pattern.step), (1, 3, 0));
+        assert_eq!(pattern.value_at(1_000_000_000_000_000), 1);
+
+        let drifting = [0, 5, 1, 6, 2, 7, 3, 8];
+        assert_eq!(Pattern::find(&drifting, 3).unwrap().value_at(101), 55);
+
+        // Three repeats of a period of three need twelve values.
+        assert_eq!(Pattern::find(&periodic, 3), None);
+    }
+}
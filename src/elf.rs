@@ -1,9 +1,14 @@
 use nom::bytes::complete::tag;
 use nom::character::complete::{alpha1, digit1, newline, space1};
-use nom::combinator::{all_consuming, map, map_res};
+use nom::combinator::{all_consuming, map, map_opt, map_res};
 use nom::multi::separated_list1;
 use nom::sequence::{preceded, separated_pair, tuple};
 use nom::IResult;
+use std::collections::VecDeque;
+pub mod symbolic;
+pub mod transpile;
+
+use crate::parallel::parallel_map;
 use crate::elf::Value::*;
 use crate::elf::Instruction::*;
 
@@ -21,14 +26,143 @@ pub enum Instruction {
     Bor(usize, Value, usize),
     Set(Value, usize, usize),
     Gt(Value, Value, usize),
-    Eq(Value, Value, usize)
+    Eq(Value, Value, usize),
+    // Extensions, only accepted by `Isa::Extended`.
+    Div(usize, Value, usize),
+    Mod(usize, Value, usize),
+    Shl(usize, Value, usize),
+    Shr(usize, Value, usize),
+    Lt(Value, Value, usize),
+    Out(usize)
+}
+
+impl Instruction {
+    /// The register this instruction writes to, if any.
+    pub fn target(&self) -> Option<usize> {
+        match *self {
+            Add(_, _, c) | Mul(_, _, c) | Ban(_, _, c) | Bor(_, _, c)
+                | Set(_, _, c) | Gt(_, _, c) | Eq(_, _, c)
+                | Div(_, _, c) | Mod(_, _, c) | Shl(_, _, c) | Shr(_, _, c) | Lt(_, _, c) => Some(c),
+            Out(_) => None
+        }
+    }
+}
+
+/// Which instruction set the parser accepts.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Isa {
+    /// The sixteen opcodes from the puzzles; anything else is a parse error.
+    Strict,
+    /// Adds `divr`/`divi`, `modr`/`modi`, `shlr`/`shli`, `shrr`/`shri`, `ltir`/`ltri`/`ltrr`
+    /// and `outr`, which appends a register to `Machine::output`.
+    Extended
+}
+
+/// How `add` and `mul` behave when a result does not fit in a register.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Arithmetic {
+    /// Overflowing is a fault that stops the machine.
+    Checked,
+    /// Every result is truncated to the given number of bits.
+    Wrapping(u32),
+    /// Results are clamped to `usize::MAX`.
+    Saturating
+}
+
+impl Default for Arithmetic {
+    fn default() -> Self {
+        Arithmetic::Wrapping(usize::BITS)
+    }
+}
+
+impl Arithmetic {
+    fn mask(self) -> usize {
+        match self {
+            Arithmetic::Wrapping(bits) if bits < usize::BITS => (1 << bits) - 1,
+            _ => usize::MAX
+        }
+    }
+
+    fn add(self, a: usize, b: usize) -> Option<usize> {
+        match self {
+            Arithmetic::Checked => a.checked_add(b),
+            Arithmetic::Wrapping(_) => Some(a.wrapping_add(b)),
+            Arithmetic::Saturating => Some(a.saturating_add(b))
+        }
+    }
+
+    fn mul(self, a: usize, b: usize) -> Option<usize> {
+        match self {
+            Arithmetic::Checked => a.checked_mul(b),
+            Arithmetic::Wrapping(_) => Some(a.wrapping_mul(b)),
+            Arithmetic::Saturating => Some(a.saturating_mul(b))
+        }
+    }
+
+    /// Left shift, treated as multiplication by a power of two.
+    fn shl(self, a: usize, b: usize) -> Option<usize> {
+        let power = u32::try_from(b).ok().and_then(|b| 2usize.checked_pow(b));
+        match (self, power) {
+            (_, Some(power)) => self.mul(a, power),
+            (_, None) if a == 0 => Some(0),
+            (Arithmetic::Checked, None) => None,
+            (Arithmetic::Wrapping(_), None) => Some(0),
+            (Arithmetic::Saturating, None) => Some(usize::MAX)
+        }
+    }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FaultKind {
+    Overflow,
+    DivisionByZero
+}
+
+/// Why the machine stopped early, and at which instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Fault {
+    pub ip: usize,
+    pub kind: FaultKind
+}
+
+/// Result of running a copy of a program from one initial register value.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SeedRun {
+    pub seed: usize,
+    pub steps: usize,
+    pub registers: [usize; 6],
+    /// Whether the program halted or hit a breakpoint within the step budget.
+    pub halted: bool,
+    pub fault: Option<Fault>
+}
+
+/// What an executed instruction overwrote, so that it can be undone.
+#[derive(Copy, Clone, Debug)]
+struct Write {
+    ip: usize,
+    register: Option<usize>,
+    previous: usize
+}
+
+/// Bounded record of the most recent writes, oldest first.
+#[derive(Clone)]
+struct History {
+    writes: VecDeque<Write>,
+    capacity: usize
+}
+
+#[derive(Clone)]
 pub struct Machine {
     pub registers: [usize; 6],
     pub ip: usize,
     pub instructions: Vec<Instruction>,
-    pub is_break: Vec<bool>
+    pub is_break: Vec<bool>,
+    /// Values written by `outr`.
+    pub output: Vec<usize>,
+    pub arithmetic: Arithmetic,
+    /// Set when iteration stopped because of a fault rather than halting.
+    pub fault: Option<Fault>,
+    history: Option<History>
 }
 
 impl Machine {
@@ -38,91 +172,191 @@ impl Machine {
             Register(i) => self.registers[i]
         }
     }
-}
 
-impl Iterator for Machine {
-    type Item = [usize; 6];
+    /// Start recording the last `capacity` executed instructions so they can be stepped back
+    /// over.  Any previously recorded history is discarded.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History { writes: VecDeque::with_capacity(capacity), capacity });
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Number of instructions that can currently be stepped back over.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.writes.len())
+    }
+
+    /// Undo the most recently executed instruction, returning the registers as they were
+    /// before it ran.
+    pub fn step_back(&mut self) -> Option<[usize; 6]> {
+        let write = self.history.as_mut()?.writes.pop_back()?;
+        match write.register {
+            Some(register) => self.registers[register] = write.previous,
+            None => { self.output.pop(); }
+        }
+        self.registers[self.ip] = write.ip;
+        Some(self.registers)
+    }
+
+    /// Step back until undoing an instruction that wrote to `register`, leaving the machine
+    /// just before that instruction ran.  Returns its instruction pointer, or `None` if no such
+    /// write is left in the history.
+    pub fn run_back_until_write(&mut self, register: usize) -> Option<usize> {
+        loop {
+            let write = *self.history.as_ref()?.writes.back()?;
+            self.step_back();
+            if write.register == Some(register) {
+                return Some(write.ip);
+            }
+        }
+    }
+
+    fn is_halted(&self) -> bool {
         let ip = self.registers[self.ip];
+        ip >= self.instructions.len() || self.is_break[ip]
+    }
+
+    /// Run a copy of this machine for each seed, with `register` initialised to the seed, for
+    /// at most `budget` steps each.  Runs are spread across all available threads and results
+    /// come back in the same order as `seeds`.
+    pub fn run_seeds(&self, register: usize, seeds: &[usize], budget: usize) -> Vec<SeedRun> {
+        parallel_map(seeds, |&seed| {
+            let mut machine = self.clone();
+            machine.registers[register] = seed;
+            machine.fault = None;
+            let steps = machine.by_ref().take(budget).count();
 
-        if ip >= self.instructions.len() || self.is_break[ip] {
-            return None;
+            SeedRun {
+                seed,
+                steps,
+                registers: machine.registers,
+                halted: machine.fault.is_none() && machine.is_halted(),
+                fault: machine.fault
+            }
+        })
+    }
+
+    /// Execute one instruction.  Returns `Ok(None)` once the machine halts or reaches a
+    /// breakpoint, and an error without touching the registers if the instruction faults.
+    pub fn step(&mut self) -> Result<Option<[usize; 6]>, Fault> {
+        if self.is_halted() {
+            return Ok(None);
         }
 
-        match self.instructions[self.registers[self.ip]] {
-            Add(a, b, c) => {
-                let b = self.value_of(b);
-                self.registers[c] = self.registers[a] + b;
-            },
-            Mul(a, b, c) => {
-                let b = self.value_of(b);
-                self.registers[c] = self.registers[a] * b;
-            },
-            Ban(a, b, c) => {
-                let b = self.value_of(b);
-                self.registers[c] = self.registers[a] & b;
-            },
-            Bor(a, b, c) => {
-                let b = self.value_of(b);
-                self.registers[c] = self.registers[a] | b;
-            },
-            Set(a, _, c) => {
-                self.registers[c] = self.value_of(a);
-            },
-            Gt(a, b, c) => {
-                let a = self.value_of(a);
-                let b = self.value_of(b);
-                self.registers[c] = if a > b { 1 } else { 0 };
+        let ip = self.registers[self.ip];
+        let instruction = self.instructions[ip];
+        let overflow = Fault { ip, kind: FaultKind::Overflow };
+        let division_by_zero = Fault { ip, kind: FaultKind::DivisionByZero };
+
+        let result = match instruction {
+            Add(a, b, _) => self.arithmetic.add(self.registers[a], self.value_of(b)).ok_or(overflow)?,
+            Mul(a, b, _) => self.arithmetic.mul(self.registers[a], self.value_of(b)).ok_or(overflow)?,
+            Ban(a, b, _) => self.registers[a] & self.value_of(b),
+            Bor(a, b, _) => self.registers[a] | self.value_of(b),
+            Set(a, _, _) => self.value_of(a),
+            Gt(a, b, _) => if self.value_of(a) > self.value_of(b) { 1 } else { 0 },
+            Eq(a, b, _) => if self.value_of(a) == self.value_of(b) { 1 } else { 0 },
+            Div(a, b, _) => self.registers[a].checked_div(self.value_of(b)).ok_or(division_by_zero)?,
+            Mod(a, b, _) => self.registers[a].checked_rem(self.value_of(b)).ok_or(division_by_zero)?,
+            Shl(a, b, _) => self.arithmetic.shl(self.registers[a], self.value_of(b)).ok_or(overflow)?,
+            Shr(a, b, _) => {
+                let b = u32::try_from(self.value_of(b)).unwrap_or(u32::MAX);
+                self.registers[a].checked_shr(b).unwrap_or(0)
             },
-            Eq(a, b, c) => {
-                let a = self.value_of(a);
-                let b = self.value_of(b);
-                self.registers[c] = if a == b { 1 } else { 0 };
+            Lt(a, b, _) => if self.value_of(a) < self.value_of(b) { 1 } else { 0 },
+            Out(a) => self.registers[a]
+        };
+
+        let register = instruction.target();
+
+        if let Some(history) = &mut self.history {
+            if history.writes.len() == history.capacity {
+                history.writes.pop_front();
+            }
+            if history.capacity > 0 {
+                let previous = register.map_or(0, |r| self.registers[r]);
+                history.writes.push_back(Write { ip, register, previous });
             }
         }
-        self.registers[self.ip] += 1;
 
-        Some(self.registers.clone())
+        match register {
+            Some(register) => self.registers[register] = result & self.arithmetic.mask(),
+            None => self.output.push(result)
+        }
+        // An instruction pointer left at `usize::MAX` stays there, past the end of the program.
+        self.registers[self.ip] = self.registers[self.ip].saturating_add(1);
+
+        Ok(Some(self.registers))
+    }
+}
+
+impl Iterator for Machine {
+    type Item = [usize; 6];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.step() {
+            Ok(registers) => registers,
+            Err(fault) => {
+                self.fault = Some(fault);
+                None
+            }
+        }
     }
 }
 
 pub fn parse_machine(input: &str) -> IResult<&str, Machine> {
+    parse_machine_with(input, Isa::Strict)
+}
+
+pub fn parse_machine_with(input: &str, isa: Isa) -> IResult<&str, Machine> {
     fn number(input: &str) -> IResult<&str, usize> {
         map_res(digit1, |s: &str| s.parse::<usize>())(input)
     }
 
-    fn instruction(input: &str) -> IResult<&str, Instruction> {
-        map(
-            tuple((
-                alpha1,
-                preceded(space1, number),
-                preceded(space1, number),
-                preceded(space1, number)
-            )),
-            |(op, a, b, c)| {
-                match op {
-                    "addr" => Add(a, Register(b), c),
-                    "addi" => Add(a, Literal(b), c),
-                    "mulr" => Mul(a, Register(b), c),
-                    "muli" => Mul(a, Literal(b), c),
-                    "banr" => Ban(a, Register(b), c),
-                    "bani" => Ban(a, Literal(b), c),
-                    "borr" => Bor(a, Register(b), c),
-                    "bori" => Bor(a, Literal(b), c),
-                    "setr" => Set(Register(a), b, c),
-                    "seti" => Set(Literal(a), b, c),
-                    "gtir" => Gt(Literal(a), Register(b), c),
-                    "gtri" => Gt(Register(a), Literal(b), c),
-                    "gtrr" => Gt(Register(a), Register(b), c),
-                    "eqir" => Eq(Literal(a), Register(b), c),
-                    "eqri" => Eq(Register(a), Literal(b), c),
-                    "eqrr" => Eq(Register(a), Register(b), c),
-                    other => panic!("Unknown instruction: {other}")
-                }
+    // Opcodes outside `isa` fail to parse, like any other unknown word.
+    let instruction = map_opt(
+        tuple((
+            alpha1,
+            preceded(space1, number),
+            preceded(space1, number),
+            preceded(space1, number)
+        )),
+        |(op, a, b, c)| {
+            match (op, isa) {
+                ("addr", _) => Some(Add(a, Register(b), c)),
+                ("addi", _) => Some(Add(a, Literal(b), c)),
+                ("mulr", _) => Some(Mul(a, Register(b), c)),
+                ("muli", _) => Some(Mul(a, Literal(b), c)),
+                ("banr", _) => Some(Ban(a, Register(b), c)),
+                ("bani", _) => Some(Ban(a, Literal(b), c)),
+                ("borr", _) => Some(Bor(a, Register(b), c)),
+                ("bori", _) => Some(Bor(a, Literal(b), c)),
+                ("setr", _) => Some(Set(Register(a), b, c)),
+                ("seti", _) => Some(Set(Literal(a), b, c)),
+                ("gtir", _) => Some(Gt(Literal(a), Register(b), c)),
+                ("gtri", _) => Some(Gt(Register(a), Literal(b), c)),
+                ("gtrr", _) => Some(Gt(Register(a), Register(b), c)),
+                ("eqir", _) => Some(Eq(Literal(a), Register(b), c)),
+                ("eqri", _) => Some(Eq(Register(a), Literal(b), c)),
+                ("eqrr", _) => Some(Eq(Register(a), Register(b), c)),
+                ("divr", Isa::Extended) => Some(Div(a, Register(b), c)),
+                ("divi", Isa::Extended) => Some(Div(a, Literal(b), c)),
+                ("modr", Isa::Extended) => Some(Mod(a, Register(b), c)),
+                ("modi", Isa::Extended) => Some(Mod(a, Literal(b), c)),
+                ("shlr", Isa::Extended) => Some(Shl(a, Register(b), c)),
+                ("shli", Isa::Extended) => Some(Shl(a, Literal(b), c)),
+                ("shrr", Isa::Extended) => Some(Shr(a, Register(b), c)),
+                ("shri", Isa::Extended) => Some(Shr(a, Literal(b), c)),
+                ("ltir", Isa::Extended) => Some(Lt(Literal(a), Register(b), c)),
+                ("ltri", Isa::Extended) => Some(Lt(Register(a), Literal(b), c)),
+                ("ltrr", Isa::Extended) => Some(Lt(Register(a), Register(b), c)),
+                ("outr", Isa::Extended) => Some(Out(a)),
+                _ => None
             }
-        )(input)
-    }
+        }
+    );
 
     map(
         all_consuming(
@@ -139,8 +373,133 @@ pub fn parse_machine(input: &str) -> IResult<&str, Machine> {
                 registers: [0; 6],
                 ip,
                 instructions,
-                is_break
+                is_break,
+                output: Vec::new(),
+                arithmetic: Arithmetic::default(),
+                fault: None,
+                history: None
             }
         }
     )(input)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_INPUT: &str = "#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5";
+
+    #[test]
+    fn test_history() {
+        let mut machine = parse_machine(TEST_INPUT).unwrap().1;
+        machine.enable_history(3);
+        let states = machine.by_ref().collect::<Vec<_>>();
+        assert_eq!(states.last(), Some(&[7, 5, 6, 0, 0, 9]));
+        assert_eq!(machine.history_len(), 3);
+
+        assert_eq!(machine.step_back(), Some(states[states.len() - 2]));
+        assert_eq!(machine.run_back_until_write(1), None);
+        assert_eq!(machine.history_len(), 0);
+
+        let mut machine = parse_machine(TEST_INPUT).unwrap().1;
+        machine.enable_history(10);
+        machine.by_ref().for_each(drop);
+        assert_eq!(machine.run_back_until_write(0), Some(4));
+        assert_eq!(machine.registers, [4, 5, 6, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let program = "#ip 5
+seti 16777215 0 1
+muli 1 65899 1
+addi 1 1 2
+mulr 2 2 3";
+
+        let run = |arithmetic| {
+            let mut machine = parse_machine(program).unwrap().1;
+            machine.arithmetic = arithmetic;
+            let registers = machine.by_ref().last().unwrap();
+            (registers, machine.fault)
+        };
+
+        let (registers, fault) = run(Arithmetic::Wrapping(24));
+        assert_eq!(registers[1..4], [16711317, 16711318, 14024676]);
+        assert_eq!(fault, None);
+
+        let (registers, fault) = run(Arithmetic::Saturating);
+        assert_eq!(registers[1..4], [1105601691285, 1105601691286, usize::MAX]);
+        assert_eq!(fault, None);
+
+        let (registers, fault) = run(Arithmetic::Checked);
+        assert_eq!(registers[1..4], [1105601691285, 1105601691286, 0]);
+        assert_eq!(fault, Some(Fault { ip: 3, kind: FaultKind::Overflow }));
+    }
+
+    #[test]
+    fn test_ip_overflow() {
+        let modes = [Arithmetic::default(), Arithmetic::Wrapping(24), Arithmetic::Saturating, Arithmetic::Checked];
+        for (arithmetic, ip) in modes.into_iter().zip([usize::MAX, 1 << 24, usize::MAX, usize::MAX]) {
+            let mut machine = parse_machine("#ip 1\nseti 18446744073709551615 0 1").unwrap().1;
+            machine.arithmetic = arithmetic;
+            assert_eq!(machine.by_ref().last().unwrap()[1], ip, "{arithmetic:?}");
+            assert_eq!((machine.fault, machine.next()), (None, None));
+        }
+
+        let mut machine = parse_machine("#ip 0\naddi 0 18446744073709551615 0").unwrap().1;
+        machine.arithmetic = Arithmetic::Saturating;
+        assert_eq!(machine.by_ref().last().unwrap()[0], usize::MAX);
+        assert_eq!(machine.fault, None);
+    }
+
+    #[test]
+    fn test_run_seeds() {
+        let program = "#ip 5
+seti 0 0 1
+addi 1 1 1
+gtrr 1 0 2
+addr 2 5 5
+seti 0 0 5";
+
+        let machine = parse_machine(program).unwrap().1;
+        let seeds = (0..10).collect::<Vec<_>>();
+        let runs = machine.run_seeds(0, &seeds, 30);
+
+        assert_eq!(runs.iter().map(|run| run.seed).collect::<Vec<_>>(), seeds);
+        assert_eq!(runs[6], SeedRun { seed: 6, steps: 28, registers: [6, 7, 1, 0, 0, 5], halted: true, fault: None });
+        assert!(runs[..7].iter().all(|run| run.halted && run.steps == 4 * run.seed + 4));
+        assert!(runs[7..].iter().all(|run| !run.halted && run.steps == 30));
+    }
+
+    #[test]
+    fn test_extended_isa() {
+        let program = "#ip 5
+seti 1000 0 1
+modi 1 7 2
+outr 2 0 0
+divi 1 7 1
+ltri 1 1 3
+addr 3 5 5
+seti 0 0 5
+shli 2 4 4";
+
+        let mut machine = parse_machine_with(program, Isa::Extended).unwrap().1;
+        machine.enable_history(8);
+        assert_eq!(machine.by_ref().last().unwrap()[1..5], [0, 2, 1, 32]);
+        assert_eq!(machine.output, [6, 2, 6, 2]);
+        assert_eq!(machine.fault, None);
+
+        assert_eq!(machine.run_back_until_write(2), Some(1));
+        assert_eq!(machine.output, [6, 2, 6]);
+
+        let Err(nom::Err::Error(error)) = parse_machine(program) else { panic!("parsed extended opcodes") };
+        assert!(error.input.starts_with("\nmodi 1 7 2"), "{error:?}");
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use itertools::Itertools;
+use crate::elf::{Instruction, Machine, Value};
+use crate::elf::symbolic::Expr::*;
+
+/// A register value in terms of the register contents at the start of a block.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Expr {
+    Const(usize),
+    Reg(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Gt(Box<Expr>, Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Mod(Box<Expr>, Box<Expr>),
+    Shl(Box<Expr>, Box<Expr>),
+    Shr(Box<Expr>, Box<Expr>)
+}
+
+impl Expr {
+    fn add(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(x.wrapping_add(y)),
+            (Const(x), b) => Expr::add(b, Const(x)),
+            (a, Const(0)) => a,
+            (Add(a, c), Const(y)) if matches!(*c, Const(_)) => {
+                let Const(x) = *c else { unreachable!() };
+                Add(a, Box::new(Const(x.wrapping_add(y))))
+            },
+            (a, b) => Add(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn mul(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(x.wrapping_mul(y)),
+            (Const(x), b) => Expr::mul(b, Const(x)),
+            (_, Const(0)) => Const(0),
+            (a, Const(1)) => a,
+            (Mul(a, c), Const(y)) if matches!(*c, Const(_)) => {
+                let Const(x) = *c else { unreachable!() };
+                Mul(a, Box::new(Const(x.wrapping_mul(y))))
+            },
+            (a, b) => Mul(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn and(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(x & y),
+            (Const(x), b) => Expr::and(b, Const(x)),
+            (_, Const(0)) => Const(0),
+            (a, b) => And(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn or(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(x | y),
+            (Const(x), b) => Expr::or(b, Const(x)),
+            (a, Const(0)) => a,
+            (a, b) => Or(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn gt(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(if x > y { 1 } else { 0 }),
+            (a, b) if a == b => Const(0),
+            (a, b) => Gt(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn eq(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(if x == y { 1 } else { 0 }),
+            (a, b) if a == b => Const(1),
+            (a, b) => Eq(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn div(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) if y != 0 => Const(x / y),
+            (a, Const(1)) => a,
+            (a, b) => Div(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn rem(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) if y != 0 => Const(x % y),
+            (_, Const(1)) => Const(0),
+            (a, b) => Mod(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn shl(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) if y < usize::BITS as usize => Const(x << y),
+            (a, Const(0)) => a,
+            (a, b) => Shl(Box::new(a), Box::new(b))
+        }
+    }
+
+    fn shr(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Const(x), Const(y)) => Const(if y < usize::BITS as usize { x >> y } else { 0 }),
+            (a, Const(0)) => a,
+            (a, b) => Shr(Box::new(a), Box::new(b))
+        }
+    }
+
+    /// Every value the expression can take, if there are only a few of them.  Comparisons are
+    /// known to be 0 or 1, which is enough to follow the `gtrr`/`addr` conditional skip idiom.
+    fn possible_values(&self) -> Option<Vec<usize>> {
+        match self {
+            Const(x) => Some(vec![*x]),
+            Gt(_, _) | Eq(_, _) => Some(vec![0, 1]),
+            Add(a, b) => {
+                let a = a.possible_values()?;
+                let b = b.possible_values()?;
+                Some(a.iter().cartesian_product(b.iter()).map(|(x, y)| x + y).unique().collect())
+            },
+            _ => None
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Or(_, _) => 1,
+            And(_, _) => 2,
+            Shl(_, _) | Shr(_, _) => 3,
+            Add(_, _) => 4,
+            Mul(a, b) if a == b => 6,
+            Mul(_, _) | Div(_, _) | Mod(_, _) => 5,
+            _ => 7
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter<'_>, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence { write!(f, "({self})") }
+        else { write!(f, "{self}") }
+    }
+
+    fn fmt_binary(f: &mut Formatter<'_>, a: &Expr, op: &str, b: &Expr, precedence: u8) -> fmt::Result {
+        a.fmt_operand(f, precedence)?;
+        write!(f, " {op} ")?;
+        b.fmt_operand(f, precedence + 1)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Const(x) => write!(f, "{x}"),
+            Reg(i) => write!(f, "r{i}"),
+            Mul(a, b) if a == b => {
+                a.fmt_operand(f, 7)?;
+                write!(f, "^2")
+            },
+            Add(a, b) => Expr::fmt_binary(f, a, "+", b, 4),
+            Mul(a, b) => Expr::fmt_binary(f, a, "*", b, 5),
+            Div(a, b) => Expr::fmt_binary(f, a, "/", b, 5),
+            Mod(a, b) => Expr::fmt_binary(f, a, "%", b, 5),
+            Shl(a, b) => Expr::fmt_binary(f, a, "<<", b, 3),
+            Shr(a, b) => Expr::fmt_binary(f, a, ">>", b, 3),
+            And(a, b) => Expr::fmt_binary(f, a, "&", b, 2),
+            Or(a, b) => Expr::fmt_binary(f, a, "|", b, 1),
+            Gt(a, b) => write!(f, "[{a} > {b}]"),
+            Eq(a, b) => write!(f, "[{a} == {b}]")
+        }
+    }
+}
+
+/// The effect of a basic block: each register as an expression over the registers on entry,
+/// anything it outputs, and the instruction pointer it continues at.
+pub struct Block {
+    pub instructions: RangeInclusive<usize>,
+    pub registers: [Expr; 6],
+    pub outputs: Vec<Expr>,
+    pub next: Expr,
+    ip: usize
+}
+
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}..={}:", self.instructions.start(), self.instructions.end())?;
+        for (i, expr) in self.registers.iter().enumerate() {
+            if i != self.ip && *expr != Reg(i) {
+                writeln!(f, "    r{i} = {expr}")?;
+            }
+        }
+        for expr in &self.outputs {
+            writeln!(f, "    out {expr}")?;
+        }
+        writeln!(f, "    goto {}", self.next)
+    }
+}
+
+/// Evaluate the straight-line code in `instructions` over expressions rather than numbers.
+pub fn evaluate(machine: &Machine, instructions: RangeInclusive<usize>) -> Block {
+    let mut registers: [Expr; 6] = std::array::from_fn(Reg);
+    let mut outputs = Vec::new();
+
+    for i in instructions.clone() {
+        registers[machine.ip] = Const(i);
+
+        let value = |v: Value, registers: &[Expr; 6]| match v {
+            Value::Literal(x) => Const(x),
+            Value::Register(r) => registers[r].clone()
+        };
+
+        let (result, c) = match machine.instructions[i] {
+            Instruction::Add(a, b, c) => (Expr::add(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Mul(a, b, c) => (Expr::mul(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Ban(a, b, c) => (Expr::and(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Bor(a, b, c) => (Expr::or(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Set(a, _, c) => (value(a, &registers), c),
+            Instruction::Gt(a, b, c) => (Expr::gt(value(a, &registers), value(b, &registers)), c),
+            Instruction::Eq(a, b, c) => (Expr::eq(value(a, &registers), value(b, &registers)), c),
+            Instruction::Div(a, b, c) => (Expr::div(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Mod(a, b, c) => (Expr::rem(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Shl(a, b, c) => (Expr::shl(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Shr(a, b, c) => (Expr::shr(registers[a].clone(), value(b, &registers)), c),
+            Instruction::Lt(a, b, c) => (Expr::gt(value(b, &registers), value(a, &registers)), c),
+            Instruction::Out(a) => {
+                outputs.push(registers[a].clone());
+                continue;
+            }
+        };
+        registers[c] = result;
+    }
+
+    let next = Expr::add(registers[machine.ip].clone(), Const(1));
+
+    Block { instructions, registers, outputs, next, ip: machine.ip }
+}
+
+/// Split the program into basic blocks and evaluate each one symbolically.  A block ends at
+/// any instruction that writes the instruction pointer, and starts at every jump target that
+/// can be worked out statically.
+pub fn analyze(machine: &Machine) -> Vec<Block> {
+    let len = machine.instructions.len();
+    let mut leaders = (0..len)
+        .filter(|&i| machine.instructions[i].target() == Some(machine.ip))
+        .map(|i| i + 1)
+        .chain([0])
+        .filter(|&i| i < len)
+        .collect::<BTreeSet<_>>();
+
+    loop {
+        let blocks = leaders.iter()
+            .map(|&start| {
+                let end = (start..len)
+                    .find(|&i| machine.instructions[i].target() == Some(machine.ip) || leaders.contains(&(i + 1)))
+                    .unwrap_or(len - 1);
+                evaluate(machine, start..=end)
+            })
+            .collect_vec();
+
+        let targets = blocks.iter()
+            .filter_map(|block| block.next.possible_values())
+            .flatten()
+            .filter(|&i| i < len && !leaders.contains(&i))
+            .collect_vec();
+
+        if targets.is_empty() {
+            return blocks;
+        }
+        leaders.extend(targets);
+    }
+}
+
+/// Human-readable summary of every basic block in the program.
+pub fn describe(machine: &Machine) -> String {
+    analyze(machine).iter().map(|block| block.to_string()).join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::parse_machine;
+
+    const TEST_INPUT: &str = "#ip 3
+addi 2 2 2
+mulr 2 2 2
+mulr 3 2 2
+muli 2 11 2
+gtrr 2 1 4
+addr 4 3 3
+seti 0 0 3
+seti 0 4 3";
+
+    #[test]
+    fn test_describe() {
+        let machine = parse_machine(TEST_INPUT).unwrap().1;
+        assert_eq!(describe(&machine), "0..=0:
+    r2 = r2 + 2
+    goto 1
+
+1..=5:
+    r2 = r2^2 * 22
+    r4 = [r2^2 * 22 > r1]
+    goto [r2^2 * 22 > r1] + 6
+
+6..=6:
+    goto 1
+
+7..=7:
+    goto 1
+");
+    }
+}
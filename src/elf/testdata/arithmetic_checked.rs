@@ -0,0 +1,57 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 5;
+
+/// Why the program stopped early, and at which instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Fault {
+    Overflow(usize)
+}
+
+pub fn step(r: &mut [usize; 6]) -> Result<bool, Fault> {
+    match r[IP] {
+        0 => { r[1] = 16777215; }
+        1 => { r[1] = r[1].checked_mul(65899).ok_or(Fault::Overflow(1))?; }
+        2 => { r[2] = r[1].checked_add(1).ok_or(Fault::Overflow(2))?; }
+        3 => { r[3] = r[2].checked_mul(r[2]).ok_or(Fault::Overflow(3))?; }
+        _ => return Ok(false)
+    }
+    r[IP] = r[IP].saturating_add(1);
+    Ok(true)
+}
+
+pub fn run(r: &mut [usize; 6]) -> Result<(), Fault> {
+    loop {
+        match r[IP] {
+            0 => {
+                r[1] = 16777215;
+                r[IP] = 1;
+                r[1] = r[1].checked_mul(65899).ok_or(Fault::Overflow(1))?;
+                r[IP] = 2;
+                r[2] = r[1].checked_add(1).ok_or(Fault::Overflow(2))?;
+                r[IP] = 3;
+                r[3] = r[2].checked_mul(r[2]).ok_or(Fault::Overflow(3))?;
+                r[IP] = 4;
+            }
+            1 => {
+                r[1] = r[1].checked_mul(65899).ok_or(Fault::Overflow(1))?;
+                r[IP] = 2;
+                r[2] = r[1].checked_add(1).ok_or(Fault::Overflow(2))?;
+                r[IP] = 3;
+                r[3] = r[2].checked_mul(r[2]).ok_or(Fault::Overflow(3))?;
+                r[IP] = 4;
+            }
+            2 => {
+                r[2] = r[1].checked_add(1).ok_or(Fault::Overflow(2))?;
+                r[IP] = 3;
+                r[3] = r[2].checked_mul(r[2]).ok_or(Fault::Overflow(3))?;
+                r[IP] = 4;
+            }
+            3 => {
+                r[3] = r[2].checked_mul(r[2]).ok_or(Fault::Overflow(3))?;
+                r[IP] = 4;
+            }
+            _ => return Ok(())
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 5;
+const MASK: usize = 0xffffff;
+
+pub fn step(r: &mut [usize; 6]) -> bool {
+    match r[IP] {
+        0 => { r[1] = 16777215; }
+        1 => { r[1] = r[1].wrapping_mul(65899) & MASK; }
+        2 => { r[2] = r[1].wrapping_add(1) & MASK; }
+        3 => { r[3] = r[2].wrapping_mul(r[2]) & MASK; }
+        _ => return false
+    }
+    r[IP] = r[IP].saturating_add(1);
+    true
+}
+
+pub fn run(r: &mut [usize; 6]) {
+    loop {
+        match r[IP] {
+            0 => {
+                r[1] = 16777215;
+                r[1] = r[1].wrapping_mul(65899) & MASK;
+                r[2] = r[1].wrapping_add(1) & MASK;
+                r[3] = r[2].wrapping_mul(r[2]) & MASK;
+                r[IP] = 4;
+            }
+            1 => {
+                r[1] = r[1].wrapping_mul(65899) & MASK;
+                r[2] = r[1].wrapping_add(1) & MASK;
+                r[3] = r[2].wrapping_mul(r[2]) & MASK;
+                r[IP] = 4;
+            }
+            2 => {
+                r[2] = r[1].wrapping_add(1) & MASK;
+                r[3] = r[2].wrapping_mul(r[2]) & MASK;
+                r[IP] = 4;
+            }
+            3 => {
+                r[3] = r[2].wrapping_mul(r[2]) & MASK;
+                r[IP] = 4;
+            }
+            _ => return
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 0;
+
+pub fn step(r: &mut [usize; 6]) -> bool {
+    match r[IP] {
+        0 => { r[1] = 5; }
+        1 => { r[2] = 6; }
+        2 => { r[0] = 3; }
+        3 => { r[3] = r[1].wrapping_add(r[2]); }
+        4 => { r[0] = r[1]; }
+        5 => { r[4] = 8; }
+        6 => { r[5] = 9; }
+        _ => return false
+    }
+    r[IP] = r[IP].saturating_add(1);
+    true
+}
+
+pub fn run(r: &mut [usize; 6]) {
+    loop {
+        match r[IP] {
+            0 => {
+                r[1] = 5;
+                r[2] = 6;
+                r[0] = 3;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            1 => {
+                r[2] = 6;
+                r[0] = 3;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            2 => {
+                r[0] = 3;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            3 => {
+                r[3] = r[1].wrapping_add(r[2]);
+                r[IP] = 4;
+            }
+            4 => {
+                r[0] = r[1];
+                r[IP] = r[IP].saturating_add(1);
+            }
+            5 => {
+                r[4] = 8;
+                r[5] = 9;
+                r[IP] = 7;
+            }
+            6 => {
+                r[5] = 9;
+                r[IP] = 7;
+            }
+            _ => return
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 5;
+
+pub fn step(r: &mut [usize; 6]) -> bool {
+    match r[IP] {
+        0 => { r[1] = 123; }
+        1 => { r[1] &= 456; }
+        2 => { r[1] = usize::from(r[1] == 72); }
+        3 => { r[5] = r[1].wrapping_add(3); }
+        4 => { r[5] = 0; }
+        5 => { r[1] = 0; }
+        6 => { r[4] = r[1] | 65536; }
+        7 => { r[1] = 1234567; }
+        8 => { r[3] = r[4] & 255; }
+        9 => { r[1] = r[1].wrapping_add(r[3]); }
+        10 => { r[1] &= 16777215; }
+        11 => { r[1] = r[1].wrapping_mul(65899); }
+        12 => { r[1] &= 16777215; }
+        13 => { r[3] = usize::from(256 > r[4]); }
+        14 => { r[5] = r[3].wrapping_add(14); }
+        15 => { r[5] = 16; }
+        16 => { r[5] = 27; }
+        17 => { r[3] = 0; }
+        18 => { r[2] = r[3].wrapping_add(1); }
+        19 => { r[2] = r[2].wrapping_mul(256); }
+        20 => { r[2] = usize::from(r[2] > r[4]); }
+        21 => { r[5] = r[2].wrapping_add(21); }
+        22 => { r[5] = 23; }
+        23 => { r[5] = 25; }
+        24 => { r[3] = r[3].wrapping_add(1); }
+        25 => { r[5] = 17; }
+        26 => { r[4] = r[3]; }
+        27 => { r[5] = 7; }
+        28 => { r[3] = usize::from(r[1] == r[0]); }
+        29 => { r[5] = r[3].wrapping_add(29); }
+        30 => { r[5] = 5; }
+        _ => return false
+    }
+    r[IP] = r[IP].saturating_add(1);
+    true
+}
+
+pub fn run(r: &mut [usize; 6]) {
+    loop {
+        match r[IP] {
+            0 => {
+                r[1] = 123;
+                r[IP] = 1;
+            }
+            1 => {
+                r[1] &= 456;
+                r[1] = usize::from(r[1] == 72);
+                r[5] = r[1].wrapping_add(3);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            2 => {
+                r[1] = usize::from(r[1] == 72);
+                r[5] = r[1].wrapping_add(3);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            3 => {
+                r[5] = r[1].wrapping_add(3);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            4 => {
+                r[5] = 0;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            5 => {
+                r[1] = 0;
+                r[IP] = 6;
+            }
+            6 => {
+                r[4] = r[1] | 65536;
+                r[1] = 1234567;
+                r[IP] = 8;
+            }
+            7 => {
+                r[1] = 1234567;
+                r[IP] = 8;
+            }
+            8 => {
+                r[3] = r[4] & 255;
+                r[1] = r[1].wrapping_add(r[3]);
+                r[1] &= 16777215;
+                r[1] = r[1].wrapping_mul(65899);
+                r[1] &= 16777215;
+                r[3] = usize::from(256 > r[4]);
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            9 => {
+                r[1] = r[1].wrapping_add(r[3]);
+                r[1] &= 16777215;
+                r[1] = r[1].wrapping_mul(65899);
+                r[1] &= 16777215;
+                r[3] = usize::from(256 > r[4]);
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            10 => {
+                r[1] &= 16777215;
+                r[1] = r[1].wrapping_mul(65899);
+                r[1] &= 16777215;
+                r[3] = usize::from(256 > r[4]);
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            11 => {
+                r[1] = r[1].wrapping_mul(65899);
+                r[1] &= 16777215;
+                r[3] = usize::from(256 > r[4]);
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            12 => {
+                r[1] &= 16777215;
+                r[3] = usize::from(256 > r[4]);
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            13 => {
+                r[3] = usize::from(256 > r[4]);
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            14 => {
+                r[5] = r[3].wrapping_add(14);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            15 => {
+                r[5] = 16;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            16 => {
+                r[5] = 27;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            17 => {
+                r[3] = 0;
+                r[IP] = 18;
+            }
+            18 => {
+                r[2] = r[3].wrapping_add(1);
+                r[2] = r[2].wrapping_mul(256);
+                r[2] = usize::from(r[2] > r[4]);
+                r[5] = r[2].wrapping_add(21);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            19 => {
+                r[2] = r[2].wrapping_mul(256);
+                r[2] = usize::from(r[2] > r[4]);
+                r[5] = r[2].wrapping_add(21);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            20 => {
+                r[2] = usize::from(r[2] > r[4]);
+                r[5] = r[2].wrapping_add(21);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            21 => {
+                r[5] = r[2].wrapping_add(21);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            22 => {
+                r[5] = 23;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            23 => {
+                r[5] = 25;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            24 => {
+                r[3] = r[3].wrapping_add(1);
+                r[5] = 17;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            25 => {
+                r[5] = 17;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            26 => {
+                r[4] = r[3];
+                r[5] = 7;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            27 => {
+                r[5] = 7;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            28 => {
+                r[3] = usize::from(r[1] == r[0]);
+                r[5] = r[3].wrapping_add(29);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            29 => {
+                r[5] = r[3].wrapping_add(29);
+                r[IP] = r[IP].saturating_add(1);
+            }
+            30 => {
+                r[5] = 5;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            _ => return
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 5;
+
+/// Why the program stopped early, and at which instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Fault {
+    Overflow(usize),
+    DivisionByZero(usize)
+}
+
+pub fn step(r: &mut [usize; 6], out: &mut Vec<usize>) -> Result<bool, Fault> {
+    match r[IP] {
+        0 => { r[1] = 1000; }
+        1 => { r[2] = r[1] % 7; }
+        2 => { out.push(r[2]); }
+        3 => { r[1] /= 7; }
+        4 => { r[3] = usize::from(r[1] < 1); }
+        5 => { r[5] = r[3].checked_add(5).ok_or(Fault::Overflow(5))?; }
+        6 => { r[5] = 0; }
+        7 => { r[4] = r[2].checked_mul(16).ok_or(Fault::Overflow(7))?; }
+        8 => { r[3] = 70; }
+        9 => { r[0] = u32::try_from(r[3]).ok().and_then(|b| r[4].checked_shr(b)).unwrap_or(0); }
+        10 => { r[2] = u32::try_from(r[3]).ok().and_then(|b| 2usize.checked_pow(b)).map_or((r[4] == 0).then_some(0), |power| r[4].checked_mul(power)).ok_or(Fault::Overflow(10))?; }
+        11 => { out.push(r[2]); }
+        12 => { r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?; }
+        _ => return Ok(false)
+    }
+    r[IP] = r[IP].saturating_add(1);
+    Ok(true)
+}
+
+pub fn run(r: &mut [usize; 6], out: &mut Vec<usize>) -> Result<(), Fault> {
+    loop {
+        match r[IP] {
+            0 => {
+                r[1] = 1000;
+                r[IP] = 1;
+            }
+            1 => {
+                r[2] = r[1] % 7;
+                out.push(r[2]);
+                r[1] /= 7;
+                r[3] = usize::from(r[1] < 1);
+                r[IP] = 5;
+                r[5] = r[3].checked_add(5).ok_or(Fault::Overflow(5))?;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            2 => {
+                out.push(r[2]);
+                r[1] /= 7;
+                r[3] = usize::from(r[1] < 1);
+                r[IP] = 5;
+                r[5] = r[3].checked_add(5).ok_or(Fault::Overflow(5))?;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            3 => {
+                r[1] /= 7;
+                r[3] = usize::from(r[1] < 1);
+                r[IP] = 5;
+                r[5] = r[3].checked_add(5).ok_or(Fault::Overflow(5))?;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            4 => {
+                r[3] = usize::from(r[1] < 1);
+                r[IP] = 5;
+                r[5] = r[3].checked_add(5).ok_or(Fault::Overflow(5))?;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            5 => {
+                r[5] = r[3].checked_add(5).ok_or(Fault::Overflow(5))?;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            6 => {
+                r[5] = 0;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            7 => {
+                r[4] = r[2].checked_mul(16).ok_or(Fault::Overflow(7))?;
+                r[3] = 70;
+                r[0] = u32::try_from(r[3]).ok().and_then(|b| r[4].checked_shr(b)).unwrap_or(0);
+                r[IP] = 10;
+                r[2] = u32::try_from(r[3]).ok().and_then(|b| 2usize.checked_pow(b)).map_or((r[4] == 0).then_some(0), |power| r[4].checked_mul(power)).ok_or(Fault::Overflow(10))?;
+                out.push(r[2]);
+                r[IP] = 12;
+                r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?;
+                r[IP] = 13;
+            }
+            8 => {
+                r[3] = 70;
+                r[0] = u32::try_from(r[3]).ok().and_then(|b| r[4].checked_shr(b)).unwrap_or(0);
+                r[IP] = 10;
+                r[2] = u32::try_from(r[3]).ok().and_then(|b| 2usize.checked_pow(b)).map_or((r[4] == 0).then_some(0), |power| r[4].checked_mul(power)).ok_or(Fault::Overflow(10))?;
+                out.push(r[2]);
+                r[IP] = 12;
+                r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?;
+                r[IP] = 13;
+            }
+            9 => {
+                r[0] = u32::try_from(r[3]).ok().and_then(|b| r[4].checked_shr(b)).unwrap_or(0);
+                r[IP] = 10;
+                r[2] = u32::try_from(r[3]).ok().and_then(|b| 2usize.checked_pow(b)).map_or((r[4] == 0).then_some(0), |power| r[4].checked_mul(power)).ok_or(Fault::Overflow(10))?;
+                out.push(r[2]);
+                r[IP] = 12;
+                r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?;
+                r[IP] = 13;
+            }
+            10 => {
+                r[2] = u32::try_from(r[3]).ok().and_then(|b| 2usize.checked_pow(b)).map_or((r[4] == 0).then_some(0), |power| r[4].checked_mul(power)).ok_or(Fault::Overflow(10))?;
+                out.push(r[2]);
+                r[IP] = 12;
+                r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?;
+                r[IP] = 13;
+            }
+            11 => {
+                out.push(r[2]);
+                r[IP] = 12;
+                r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?;
+                r[IP] = 13;
+            }
+            12 => {
+                r[4] = r[4].checked_rem(r[1]).ok_or(Fault::DivisionByZero(12))?;
+                r[IP] = 13;
+            }
+            _ => return Ok(())
+        }
+    }
+}
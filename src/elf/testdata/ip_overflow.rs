@@ -0,0 +1,29 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 1;
+
+pub fn step(r: &mut [usize; 6]) -> bool {
+    match r[IP] {
+        0 => { r[2] = 5; }
+        1 => { r[1] = 18446744073709551615; }
+        _ => return false
+    }
+    r[IP] = r[IP].saturating_add(1);
+    true
+}
+
+pub fn run(r: &mut [usize; 6]) {
+    loop {
+        match r[IP] {
+            0 => {
+                r[2] = 5;
+                r[IP] = 1;
+            }
+            1 => {
+                r[1] = 18446744073709551615;
+                r[IP] = r[IP].saturating_add(1);
+            }
+            _ => return
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 5;
+
+/// Why the program stopped early, and at which instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Fault {
+    Overflow(usize)
+}
+
+pub fn step(r: &mut [usize; 6]) -> Result<bool, Fault> {
+    match r[IP] {
+        0 => { r[3] = (r[0] == 0).then_some(0).ok_or(Fault::Overflow(0))?; }
+        1 => { r[1] = 3; }
+        2 => { r[2] = (r[1] == 0).then_some(0).ok_or(Fault::Overflow(2))?; }
+        _ => return Ok(false)
+    }
+    r[IP] = r[IP].saturating_add(1);
+    Ok(true)
+}
+
+pub fn run(r: &mut [usize; 6]) -> Result<(), Fault> {
+    loop {
+        match r[IP] {
+            0 => {
+                r[3] = (r[0] == 0).then_some(0).ok_or(Fault::Overflow(0))?;
+                r[1] = 3;
+                r[IP] = 2;
+                r[2] = (r[1] == 0).then_some(0).ok_or(Fault::Overflow(2))?;
+                r[IP] = 3;
+            }
+            1 => {
+                r[1] = 3;
+                r[IP] = 2;
+                r[2] = (r[1] == 0).then_some(0).ok_or(Fault::Overflow(2))?;
+                r[IP] = 3;
+            }
+            2 => {
+                r[2] = (r[1] == 0).then_some(0).ok_or(Fault::Overflow(2))?;
+                r[IP] = 3;
+            }
+            _ => return Ok(())
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// Generated by adventofcode2018::elf::transpile.
+
+const IP: usize = 5;
+
+pub fn step(r: &mut [usize; 6]) -> bool {
+    match r[IP] {
+        0 => { r[3] = 0; }
+        1 => { r[1] = 3; }
+        2 => { r[2] = 0; }
+        _ => return false
+    }
+    r[IP] = r[IP].saturating_add(1);
+    true
+}
+
+pub fn run(r: &mut [usize; 6]) {
+    loop {
+        match r[IP] {
+            0 => {
+                r[3] = 0;
+                r[1] = 3;
+                r[2] = 0;
+                r[IP] = 3;
+            }
+            1 => {
+                r[1] = 3;
+                r[2] = 0;
+                r[IP] = 3;
+            }
+            2 => {
+                r[2] = 0;
+                r[IP] = 3;
+            }
+            _ => return
+        }
+    }
+}
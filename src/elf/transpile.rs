@@ -0,0 +1,588 @@
+use std::fmt::Write;
+use itertools::Itertools;
+use crate::elf::{Arithmetic, Instruction, Machine, Value};
+use crate::elf::symbolic::analyze;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Operand {
+    Literal(usize),
+    Register(usize)
+}
+
+impl Operand {
+    fn code(self) -> String {
+        match self {
+            Operand::Literal(x) => x.to_string(),
+            Operand::Register(r) => format!("r[{r}]")
+        }
+    }
+
+    /// As the receiver of a method call, where a bare literal would have an ambiguous type.
+    fn receiver(self) -> String {
+        match self {
+            Operand::Literal(x) => format!("{x}usize"),
+            Operand::Register(r) => format!("r[{r}]")
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum BinOp { Add, Mul, Ban, Bor, Div, Mod, Shl, Shr }
+
+impl BinOp {
+    /// Right operand that leaves the left one unchanged.
+    fn identity(self) -> Option<usize> {
+        match self {
+            BinOp::Add | BinOp::Bor | BinOp::Shl | BinOp::Shr => Some(0),
+            BinOp::Mul | BinOp::Div => Some(1),
+            BinOp::Ban => Some(usize::MAX),
+            BinOp::Mod => None
+        }
+    }
+
+    /// Right operand that makes the result itself.
+    fn zero(self) -> Option<usize> {
+        match self {
+            BinOp::Mul | BinOp::Ban => Some(0),
+            _ => None
+        }
+    }
+
+    fn commutative(self) -> bool {
+        matches!(self, BinOp::Add | BinOp::Mul | BinOp::Ban | BinOp::Bor)
+    }
+
+    fn idempotent(self) -> bool {
+        matches!(self, BinOp::Ban | BinOp::Bor)
+    }
+
+    /// The result as `Machine::step` computes it, or the fault it raises instead.
+    fn fold(self, arithmetic: Arithmetic, x: usize, y: usize) -> Result<usize, &'static str> {
+        match self {
+            BinOp::Add => arithmetic.add(x, y).ok_or(OVERFLOW),
+            BinOp::Mul => arithmetic.mul(x, y).ok_or(OVERFLOW),
+            BinOp::Ban => Ok(x & y),
+            BinOp::Bor => Ok(x | y),
+            BinOp::Div => x.checked_div(y).ok_or(DIVISION_BY_ZERO),
+            BinOp::Mod => x.checked_rem(y).ok_or(DIVISION_BY_ZERO),
+            BinOp::Shl => arithmetic.shl(x, y).ok_or(OVERFLOW),
+            BinOp::Shr => Ok(x.checked_shr(y.try_into().unwrap_or(u32::MAX)).unwrap_or(0))
+        }
+    }
+}
+
+const OVERFLOW: &str = "Overflow";
+const DIVISION_BY_ZERO: &str = "DivisionByZero";
+
+/// What an instruction computes, before it is stored.
+enum Computed {
+    /// Known while transpiling, or a copy of a register.
+    Operand(Operand),
+    /// `a op b` for a plain Rust operator, which can become `a op= b`.
+    Operator(Operand, &'static str, Operand),
+    /// A comparison, which is always 0 or 1.
+    Flag(String),
+    /// A method call chain, along with the fault it can return.
+    Expression(String, Option<&'static str>),
+    /// The instruction always faults.
+    Fault(&'static str)
+}
+
+/// Rust code for one instruction, and the fault it can return, if any.
+struct Statement {
+    code: String,
+    fault: Option<&'static str>
+}
+
+/// Rust statements for the instruction at `ip`.  The instruction pointer register is known to
+/// hold `ip` while it runs, so reads of it become literals.  Identities are folded away so the
+/// output stays free of lints like `x * 1` or `r[1] = r[1]`.  Arithmetic follows
+/// `machine.arithmetic`: results are masked to the register width, and anything that makes the
+/// interpreter fault returns a `Fault` naming the instruction instead of panicking.
+fn statement(machine: &Machine, ip: usize) -> Statement {
+    let arithmetic = machine.arithmetic;
+    let operand = |v: Value| match v {
+        Value::Literal(x) => Operand::Literal(x),
+        Value::Register(r) if r == machine.ip => Operand::Literal(ip),
+        Value::Register(r) => Operand::Register(r)
+    };
+    let register = |r: usize| operand(Value::Register(r));
+
+    let binary = |c: usize, a: Operand, op: BinOp, b: Operand| -> Computed {
+        if let (Operand::Literal(x), Operand::Literal(y)) = (a, b) {
+            return match op.fold(arithmetic, x, y) {
+                Ok(folded) => Computed::Operand(Operand::Literal(folded)),
+                Err(fault) => Computed::Fault(fault)
+            };
+        }
+
+        match (a, b) {
+            (a, b) if a == b && op.idempotent() => return Computed::Operand(a),
+            (a, Operand::Literal(x)) if Some(x) == op.identity() => return Computed::Operand(a),
+            (Operand::Literal(x), b) if Some(x) == op.identity() && op.commutative() => return Computed::Operand(b),
+            (_, Operand::Literal(x)) if Some(x) == op.zero() => return Computed::Operand(Operand::Literal(x)),
+            (Operand::Literal(x), _) if Some(x) == op.zero() && op.commutative() => return Computed::Operand(Operand::Literal(x)),
+            _ => ()
+        }
+
+        // Keep registers on the left, and the target first, where the order does not matter.
+        let (a, b) = if op.commutative() && (b == Operand::Register(c) || matches!(a, Operand::Literal(_))) {
+            (b, a)
+        }
+        else {
+            (a, b)
+        };
+        let checked = |method: &str, fault: &'static str| Computed::Expression(
+            format!("{}.{method}({}).ok_or(Fault::{fault}({ip}))?", a.receiver(), b.code()),
+            Some(fault)
+        );
+        let method = |method: &str| Computed::Expression(format!("{}.{method}({})", a.receiver(), b.code()), None);
+
+        match (op, arithmetic) {
+            (BinOp::Add, Arithmetic::Checked) => checked("checked_add", OVERFLOW),
+            (BinOp::Add, Arithmetic::Wrapping(_)) => method("wrapping_add"),
+            (BinOp::Add, Arithmetic::Saturating) => method("saturating_add"),
+            (BinOp::Mul, Arithmetic::Checked) => checked("checked_mul", OVERFLOW),
+            (BinOp::Mul, Arithmetic::Wrapping(_)) => method("wrapping_mul"),
+            (BinOp::Mul, Arithmetic::Saturating) => method("saturating_mul"),
+            (BinOp::Ban, _) => Computed::Operator(a, "&", b),
+            (BinOp::Bor, _) => Computed::Operator(a, "|", b),
+            (BinOp::Div, _) if b != Operand::Literal(0) && matches!(b, Operand::Literal(_)) => Computed::Operator(a, "/", b),
+            (BinOp::Div, _) => checked("checked_div", DIVISION_BY_ZERO),
+            (BinOp::Mod, _) if b != Operand::Literal(0) && matches!(b, Operand::Literal(_)) => Computed::Operator(a, "%", b),
+            (BinOp::Mod, _) => checked("checked_rem", DIVISION_BY_ZERO),
+            (BinOp::Shr, _) => match b {
+                Operand::Literal(y) if y < usize::BITS as usize => Computed::Operator(a, ">>", b),
+                Operand::Literal(_) => Computed::Operand(Operand::Literal(0)),
+                Operand::Register(_) => Computed::Expression(
+                    format!("u32::try_from({}).ok().and_then(|b| {}.checked_shr(b)).unwrap_or(0)", b.code(), a.receiver()),
+                    None
+                )
+            },
+            // Shifting left is multiplying by a power of two, which may overflow.
+            (BinOp::Shl, _) => match (b, arithmetic) {
+                (Operand::Literal(y), Arithmetic::Wrapping(_)) if y < usize::BITS as usize => Computed::Operator(a, "<<", b),
+                (Operand::Literal(_), Arithmetic::Wrapping(_)) => Computed::Operand(Operand::Literal(0)),
+                (b, Arithmetic::Wrapping(_)) => Computed::Expression(
+                    format!("u32::try_from({}).ok().and_then(|b| {}.checked_shl(b)).unwrap_or(0)", b.code(), a.receiver()),
+                    None
+                ),
+                (Operand::Literal(y), Arithmetic::Checked) if y < usize::BITS as usize => Computed::Expression(
+                    format!("{}.checked_mul({}).ok_or(Fault::{OVERFLOW}({ip}))?", a.receiver(), 1usize << y),
+                    Some(OVERFLOW)
+                ),
+                (Operand::Literal(y), Arithmetic::Saturating) if y < usize::BITS as usize => Computed::Expression(
+                    format!("{}.saturating_mul({})", a.receiver(), 1usize << y),
+                    None
+                ),
+                // Past the register width only zero stays in range.
+                (Operand::Literal(_), Arithmetic::Checked) => Computed::Expression(
+                    format!("({} == 0).then_some(0).ok_or(Fault::{OVERFLOW}({ip}))?", a.code()),
+                    Some(OVERFLOW)
+                ),
+                (Operand::Literal(_), Arithmetic::Saturating) => Computed::Expression(
+                    format!("if {} == 0 {{ 0 }} else {{ usize::MAX }}", a.code()),
+                    None
+                ),
+                (b, Arithmetic::Checked) => Computed::Expression(
+                    format!(
+                        "u32::try_from({}).ok().and_then(|b| 2usize.checked_pow(b)).map_or(({} == 0).then_some(0), |power| {}.checked_mul(power)).ok_or(Fault::{OVERFLOW}({ip}))?",
+                        b.receiver(), a.code(), a.receiver()
+                    ),
+                    Some(OVERFLOW)
+                ),
+                (b, Arithmetic::Saturating) => Computed::Expression(
+                    format!(
+                        "u32::try_from({}).ok().and_then(|b| 2usize.checked_pow(b)).map_or(if {} == 0 {{ 0 }} else {{ usize::MAX }}, |power| {}.saturating_mul(power))",
+                        b.receiver(), a.code(), a.receiver()
+                    ),
+                    None
+                )
+            }
+        }
+    };
+
+    let compare = |a: Operand, op: &str, b: Operand, fold: fn(usize, usize) -> bool| match (a, b) {
+        (Operand::Literal(x), Operand::Literal(y)) => Computed::Operand(Operand::Literal(fold(x, y) as usize)),
+        (a, b) if a == b => Computed::Operand(Operand::Literal(fold(0, 0) as usize)),
+        (a, b) => Computed::Flag(format!("usize::from({} {op} {})", a.code(), b.code()))
+    };
+
+    let (c, computed) = match machine.instructions[ip] {
+        Instruction::Add(a, b, c) => (c, binary(c, register(a), BinOp::Add, operand(b))),
+        Instruction::Mul(a, b, c) => (c, binary(c, register(a), BinOp::Mul, operand(b))),
+        Instruction::Ban(a, b, c) => (c, binary(c, register(a), BinOp::Ban, operand(b))),
+        Instruction::Bor(a, b, c) => (c, binary(c, register(a), BinOp::Bor, operand(b))),
+        Instruction::Set(a, _, c) => (c, Computed::Operand(operand(a))),
+        Instruction::Gt(a, b, c) => (c, compare(operand(a), ">", operand(b), |x, y| x > y)),
+        Instruction::Eq(a, b, c) => (c, compare(operand(a), "==", operand(b), |x, y| x == y)),
+        Instruction::Div(a, b, c) => (c, binary(c, register(a), BinOp::Div, operand(b))),
+        Instruction::Mod(a, b, c) => (c, binary(c, register(a), BinOp::Mod, operand(b))),
+        Instruction::Shl(a, b, c) => (c, binary(c, register(a), BinOp::Shl, operand(b))),
+        Instruction::Shr(a, b, c) => (c, binary(c, register(a), BinOp::Shr, operand(b))),
+        Instruction::Lt(a, b, c) => (c, compare(operand(a), "<", operand(b), |x, y| x < y)),
+        Instruction::Out(a) => {
+            return Statement { code: format!("out.push({});", register(a).code()), fault: None };
+        }
+    };
+
+    let mask = arithmetic.mask();
+    let masked = |code: String| if mask == usize::MAX { code } else { format!("{code} & MASK") };
+    let (code, fault) = match computed {
+        Computed::Operand(Operand::Literal(x)) => (format!("r[{c}] = {};", x & mask), None),
+        Computed::Operand(Operand::Register(r)) if r == c && mask == usize::MAX => (String::new(), None),
+        Computed::Operand(Operand::Register(r)) if r == c => (format!("r[{c}] &= MASK;"), None),
+        Computed::Operand(Operand::Register(r)) => (format!("r[{c}] = {};", masked(format!("r[{r}]"))), None),
+        Computed::Operator(a, symbol, b) if a == Operand::Register(c) && mask == usize::MAX => {
+            (format!("r[{c}] {symbol}= {};", b.code()), None)
+        },
+        Computed::Operator(a, symbol, b) if mask == usize::MAX => (format!("r[{c}] = {} {symbol} {};", a.code(), b.code()), None),
+        Computed::Operator(a, symbol, b) => (format!("r[{c}] = ({} {symbol} {}) & MASK;", a.code(), b.code()), None),
+        Computed::Flag(_) if mask == 0 => (format!("r[{c}] = 0;"), None),
+        Computed::Flag(code) => (format!("r[{c}] = {code};"), None),
+        Computed::Expression(code, fault) => (format!("r[{c}] = {};", masked(code)), fault),
+        Computed::Fault(fault) => (format!("return Err(Fault::{fault}({ip}));"), Some(fault))
+    };
+
+    Statement { code, fault }
+}
+
+/// Compile the machine's program to Rust source operating on a `[usize; 6]`.
+///
+/// The output defines `step`, which executes exactly one instruction like `Machine::step`, and
+/// `run`, which executes whole basic blocks per dispatch until the program halts.  Programs that
+/// use `outr` also take an `out: &mut Vec<usize>` parameter.  Programs that can fault under
+/// `machine.arithmetic` or by dividing by zero return a `Result`, with a generated `Fault` enum
+/// holding the instruction pointer; as with the interpreter, the faulting instruction leaves the
+/// registers alone and the instruction pointer on itself.  Breakpoints are not carried over.
+pub fn transpile(machine: &Machine) -> String {
+    let len = machine.instructions.len();
+    let statements = (0..len).map(|ip| statement(machine, ip)).collect_vec();
+    let block_end = analyze(machine).into_iter()
+        .flat_map(|block| {
+            let end = *block.instructions.end();
+            block.instructions.map(move |i| (i, end))
+        })
+        .sorted()
+        .map(|(_, end)| end)
+        .collect_vec();
+    let params = if machine.instructions.iter().any(|i| matches!(i, Instruction::Out(_))) {
+        "r: &mut [usize; 6], out: &mut Vec<usize>"
+    }
+    else {
+        "r: &mut [usize; 6]"
+    };
+    let faults = [OVERFLOW, DIVISION_BY_ZERO].into_iter()
+        .filter(|&fault| statements.iter().any(|statement| statement.fault == Some(fault)))
+        .collect_vec();
+    let (step_result, halted, stepped, run_result, finished) = if faults.is_empty() {
+        ("bool", "false", "true", "", "")
+    }
+    else {
+        ("Result<bool, Fault>", "Ok(false)", "Ok(true)", " -> Result<(), Fault>", " Ok(())")
+    };
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by adventofcode2018::elf::transpile.").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "const IP: usize = {};", machine.ip).unwrap();
+    if machine.arithmetic.mask() != usize::MAX {
+        writeln!(out, "const MASK: usize = {:#x};", machine.arithmetic.mask()).unwrap();
+    }
+    writeln!(out).unwrap();
+
+    if !faults.is_empty() {
+        writeln!(out, "/// Why the program stopped early, and at which instruction.").unwrap();
+        writeln!(out, "#[derive(Copy, Clone, Eq, PartialEq, Debug)]").unwrap();
+        writeln!(out, "pub enum Fault {{").unwrap();
+        writeln!(out, "{}", faults.iter().map(|fault| format!("    {fault}(usize)")).join(",\n")).unwrap();
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+    }
+
+    writeln!(out, "pub fn step({params}) -> {step_result} {{").unwrap();
+    writeln!(out, "    match r[IP] {{").unwrap();
+    for (ip, statement) in statements.iter().enumerate() {
+        if statement.code.is_empty() {
+            writeln!(out, "        {ip} => {{}}").unwrap();
+        }
+        else {
+            writeln!(out, "        {ip} => {{ {} }}", statement.code).unwrap();
+        }
+    }
+    writeln!(out, "        _ => return {halted}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    r[IP] = r[IP].saturating_add(1);").unwrap();
+    writeln!(out, "    {stepped}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "pub fn run({params}){run_result} {{").unwrap();
+    writeln!(out, "    loop {{").unwrap();
+    writeln!(out, "        match r[IP] {{").unwrap();
+    for (start, &end) in block_end.iter().enumerate() {
+        writeln!(out, "            {start} => {{").unwrap();
+        for (ip, statement) in statements.iter().enumerate().take(end + 1).skip(start) {
+            // A fault has to leave the instruction pointer on the faulting instruction.
+            if statement.fault.is_some() && ip != start {
+                writeln!(out, "                r[IP] = {ip};").unwrap();
+            }
+            if !statement.code.is_empty() {
+                writeln!(out, "                {}", statement.code).unwrap();
+            }
+        }
+        if machine.instructions[end].target() == Some(machine.ip) {
+            writeln!(out, "                r[IP] = r[IP].saturating_add(1);").unwrap();
+        }
+        else {
+            writeln!(out, "                r[IP] = {};", end + 1).unwrap();
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+    writeln!(out, "            _ => return{finished}").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::{parse_machine, parse_machine_with, Fault, FaultKind, Isa};
+
+    mod day19_sample {
+        include!("testdata/day19_sample.rs");
+    }
+
+    mod day21_sample {
+        include!("testdata/day21_sample.rs");
+    }
+
+    mod arithmetic_wrapping {
+        include!("testdata/arithmetic_wrapping.rs");
+    }
+
+    mod arithmetic_checked {
+        include!("testdata/arithmetic_checked.rs");
+    }
+
+    mod extended_sample {
+        include!("testdata/extended_sample.rs");
+    }
+
+    mod extended_checked {
+        include!("testdata/extended_checked.rs");
+    }
+
+    mod ip_overflow {
+        include!("testdata/ip_overflow.rs");
+    }
+
+    mod wide_shift_wrapping {
+        include!("testdata/wide_shift_wrapping.rs");
+    }
+
+    mod wide_shift_checked {
+        include!("testdata/wide_shift_checked.rs");
+    }
+
+    mod wide_shift_saturating {
+        include!("testdata/wide_shift_saturating.rs");
+    }
+
+    const DAY19_SAMPLE: &str = "#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5";
+
+    const DAY21_SAMPLE: &str = "#ip 5
+seti 123 0 1
+bani 1 456 1
+eqri 1 72 1
+addr 1 5 5
+seti 0 0 5
+seti 0 0 1
+bori 1 65536 4
+seti 1234567 3 1
+bani 4 255 3
+addr 1 3 1
+bani 1 16777215 1
+muli 1 65899 1
+bani 1 16777215 1
+gtir 256 4 3
+addr 3 5 5
+addi 5 1 5
+seti 27 6 5
+seti 0 1 3
+addi 3 1 2
+muli 2 256 2
+gtrr 2 4 2
+addr 2 5 5
+addi 5 1 5
+seti 25 2 5
+addi 3 1 3
+seti 17 1 5
+setr 3 4 4
+seti 7 3 5
+eqrr 1 0 3
+addr 3 5 5
+seti 5 4 5";
+
+    const ARITHMETIC_SAMPLE: &str = "#ip 5
+seti 16777215 0 1
+muli 1 65899 1
+addi 1 1 2
+mulr 2 2 3";
+
+    /// Writes the remainders of 1000 divided by 7, shifts past the register width and ends by
+    /// dividing by zero.
+    const EXTENDED_SAMPLE: &str = "#ip 5
+seti 1000 0 1
+modi 1 7 2
+outr 2 0 0
+divi 1 7 1
+ltri 1 1 3
+addr 3 5 5
+seti 0 0 5
+shli 2 4 4
+seti 70 0 3
+shrr 4 3 0
+shlr 4 3 2
+outr 2 0 0
+modr 4 1 4";
+
+    /// Jumps to the last address, leaving the instruction pointer nowhere to go.
+    const IP_OVERFLOW_SAMPLE: &str = "#ip 1
+seti 5 0 2
+addi 1 18446744073709551615 1";
+
+    /// Shifts left by literals too wide for an `i32`, first a zero and then a nonzero register.
+    const WIDE_SHIFT_SAMPLE: &str = "#ip 5
+shli 0 2147483648 3
+seti 3 0 1
+shli 1 18446744073709551615 2";
+
+    fn with_arithmetic(program: &str, arithmetic: Arithmetic) -> Machine {
+        let mut machine = parse_machine_with(program, Isa::Extended).unwrap().1;
+        machine.arithmetic = arithmetic;
+        machine
+    }
+
+    fn run_to_end(mut machine: Machine) -> Option<Fault> {
+        machine.by_ref().for_each(drop);
+        machine.fault
+    }
+
+    /// Step the generated code alongside the interpreter, then run it in one go, checking that
+    /// both end with the same registers, output and fault.
+    fn check(
+        mut machine: Machine,
+        step: impl Fn(&mut [usize; 6], &mut Vec<usize>) -> Result<bool, Fault>,
+        run: impl Fn(&mut [usize; 6], &mut Vec<usize>) -> Result<(), Fault>
+    ) {
+        let (mut registers, mut output) = (machine.registers, Vec::new());
+        let (mut expected, mut expected_output) = (machine.registers, Vec::new());
+        let result = run(&mut expected, &mut expected_output);
+
+        for state in machine.by_ref() {
+            assert_eq!(step(&mut registers, &mut output), Ok(true));
+            assert_eq!(registers, state);
+        }
+        let end = machine.fault.map_or(Ok(false), Err);
+        assert_eq!(step(&mut registers, &mut output), end);
+        assert_eq!((registers, output), (machine.registers, machine.output.clone()));
+        assert_eq!(result, end.map(drop));
+        assert_eq!((expected, expected_output), (machine.registers, machine.output));
+    }
+
+    #[test]
+    fn test_generated_source() {
+        let machine = parse_machine(DAY19_SAMPLE).unwrap().1;
+        assert_eq!(transpile(&machine), include_str!("testdata/day19_sample.rs"));
+
+        let machine = parse_machine(DAY21_SAMPLE).unwrap().1;
+        assert_eq!(transpile(&machine), include_str!("testdata/day21_sample.rs"));
+
+        let machine = with_arithmetic(ARITHMETIC_SAMPLE, Arithmetic::Wrapping(24));
+        assert_eq!(transpile(&machine), include_str!("testdata/arithmetic_wrapping.rs"));
+
+        let machine = with_arithmetic(ARITHMETIC_SAMPLE, Arithmetic::Checked);
+        assert_eq!(transpile(&machine), include_str!("testdata/arithmetic_checked.rs"));
+
+        let machine = with_arithmetic(EXTENDED_SAMPLE, Arithmetic::default());
+        assert_eq!(transpile(&machine), include_str!("testdata/extended_sample.rs"));
+
+        let machine = with_arithmetic(EXTENDED_SAMPLE, Arithmetic::Checked);
+        assert_eq!(transpile(&machine), include_str!("testdata/extended_checked.rs"));
+
+        let machine = with_arithmetic(IP_OVERFLOW_SAMPLE, Arithmetic::Saturating);
+        assert_eq!(transpile(&machine), include_str!("testdata/ip_overflow.rs"));
+
+        let machine = with_arithmetic(WIDE_SHIFT_SAMPLE, Arithmetic::default());
+        assert_eq!(transpile(&machine), include_str!("testdata/wide_shift_wrapping.rs"));
+
+        let machine = with_arithmetic(WIDE_SHIFT_SAMPLE, Arithmetic::Checked);
+        assert_eq!(transpile(&machine), include_str!("testdata/wide_shift_checked.rs"));
+
+        let machine = with_arithmetic(WIDE_SHIFT_SAMPLE, Arithmetic::Saturating);
+        assert_eq!(transpile(&machine), include_str!("testdata/wide_shift_saturating.rs"));
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        let machine = parse_machine(DAY19_SAMPLE).unwrap().1;
+        check(machine, |r, _| Ok(day19_sample::step(r)), |r, _| { day19_sample::run(r); Ok(()) });
+
+        // Halt at the first value the program compares against r0.
+        let mut machine = parse_machine(DAY21_SAMPLE).unwrap().1;
+        machine.is_break[28] = true;
+        let halt = machine.last().unwrap()[1];
+
+        let mut machine = parse_machine(DAY21_SAMPLE).unwrap().1;
+        machine.registers[0] = halt;
+        check(machine, |r, _| Ok(day21_sample::step(r)), |r, _| { day21_sample::run(r); Ok(()) });
+
+        let machine = with_arithmetic(ARITHMETIC_SAMPLE, Arithmetic::Wrapping(24));
+        check(machine, |r, _| Ok(arithmetic_wrapping::step(r)), |r, _| { arithmetic_wrapping::run(r); Ok(()) });
+
+        let overflow = |arithmetic_checked::Fault::Overflow(ip)| Fault { ip, kind: FaultKind::Overflow };
+        let machine = with_arithmetic(ARITHMETIC_SAMPLE, Arithmetic::Checked);
+        check(machine, |r, _| arithmetic_checked::step(r).map_err(overflow), |r, _| arithmetic_checked::run(r).map_err(overflow));
+
+        let division_by_zero = |extended_sample::Fault::DivisionByZero(ip)| Fault { ip, kind: FaultKind::DivisionByZero };
+        let machine = with_arithmetic(EXTENDED_SAMPLE, Arithmetic::default());
+        assert_eq!(run_to_end(machine.clone()), Some(Fault { ip: 12, kind: FaultKind::DivisionByZero }));
+        check(machine, |r, out| extended_sample::step(r, out).map_err(division_by_zero), |r, out| extended_sample::run(r, out).map_err(division_by_zero));
+
+        // Shifting past the register width overflows before the division is reached.
+        let fault = |fault| match fault {
+            extended_checked::Fault::Overflow(ip) => Fault { ip, kind: FaultKind::Overflow },
+            extended_checked::Fault::DivisionByZero(ip) => Fault { ip, kind: FaultKind::DivisionByZero }
+        };
+        let machine = with_arithmetic(EXTENDED_SAMPLE, Arithmetic::Checked);
+        assert_eq!(run_to_end(machine.clone()), Some(Fault { ip: 10, kind: FaultKind::Overflow }));
+        check(machine, |r, out| extended_checked::step(r, out).map_err(fault), |r, out| extended_checked::run(r, out).map_err(fault));
+
+        let machine = with_arithmetic(IP_OVERFLOW_SAMPLE, Arithmetic::Saturating);
+        check(machine, |r, _| Ok(ip_overflow::step(r)), |r, _| { ip_overflow::run(r); Ok(()) });
+
+        let machine = with_arithmetic(WIDE_SHIFT_SAMPLE, Arithmetic::default());
+        assert_eq!(run_to_end(machine.clone()), None);
+        check(machine, |r, _| Ok(wide_shift_wrapping::step(r)), |r, _| { wide_shift_wrapping::run(r); Ok(()) });
+
+        let overflow = |wide_shift_checked::Fault::Overflow(ip)| Fault { ip, kind: FaultKind::Overflow };
+        let machine = with_arithmetic(WIDE_SHIFT_SAMPLE, Arithmetic::Checked);
+        assert_eq!(run_to_end(machine.clone()), Some(Fault { ip: 2, kind: FaultKind::Overflow }));
+        check(machine, |r, _| wide_shift_checked::step(r).map_err(overflow), |r, _| wide_shift_checked::run(r).map_err(overflow));
+
+        let mut machine = with_arithmetic(WIDE_SHIFT_SAMPLE, Arithmetic::Saturating);
+        check(machine.clone(), |r, _| Ok(wide_shift_saturating::step(r)), |r, _| { wide_shift_saturating::run(r); Ok(()) });
+        assert_eq!(machine.by_ref().last().unwrap()[2], usize::MAX);
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::Path;
+use std::{fs, io};
+
+/// An RGB raster that can be written as binary PPM or as PNG.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: [u8; 3]) -> Image {
+        Image { width, height, pixels: vec![background; width * height] }
+    }
+
+    /// Build an image from a grid, colouring every cell and blowing it up to `scale` pixels square.
+    pub fn from_grid<T>(grid: &[Vec<T>], scale: usize, colour: impl Fn(&T) -> [u8; 3]) -> Image {
+        let height = grid.len() * scale;
+        let width = grid.first().map_or(0, |row| row.len()) * scale;
+
+        let pixels = grid.iter()
+            .flat_map(|row| {
+                let line = row.iter()
+                    .flat_map(|cell| std::iter::repeat_n(colour(cell), scale))
+                    .collect::<Vec<_>>();
+                std::iter::repeat_n(line, scale).flatten()
+            })
+            .collect();
+
+        Image { width, height, pixels }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, colour: [u8; 3]) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend(self.pixels.iter().flatten());
+        out
+    }
+
+    /// PNG with the image data in stored (uncompressed) deflate blocks, which keeps the encoder
+    /// small at the cost of file size.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((3 * self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width.max(1)).take(self.height) {
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut zlib = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend([1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            let len = block.len() as u16;
+            zlib.push(u8::from(blocks.peek().is_none()));
+            zlib.extend(len.to_le_bytes());
+            zlib.extend((!len).to_le_bytes());
+            zlib.extend(block);
+        }
+        zlib.extend(adler32(&raw).to_be_bytes());
+
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut out = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        write_chunk(&mut out, b"IHDR", &header);
+        write_chunk(&mut out, b"IDAT", &zlib);
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    /// Write the image as PNG if the path ends in `.png` and as PPM otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let bytes = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+            self.to_png()
+        }
+        else {
+            self.to_ppm()
+        };
+        fs::write(path, bytes)
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoders() {
+        let mut image = Image::new(2, 1, [0, 0, 0]);
+        image.set(1, 0, [255, 128, 1]);
+
+        assert_eq!(image.to_ppm(), b"P6\n2 1\n255\n\x00\x00\x00\xff\x80\x01");
+
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let png = image.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[png.len() - 12..], b"\x00\x00\x00\x00IEND\xae\x42\x60\x82");
+
+        // Filter byte, then both pixels, stored in a single final block.
+        let idat = png.windows(4).position(|w| w == b"IDAT").unwrap() + 4;
+        assert_eq!(&png[idat..idat + 14], b"\x78\x01\x01\x07\x00\xf8\xff\x00\x00\x00\x00\xff\x80\x01");
+    }
+
+    #[test]
+    fn test_from_grid() {
+        let grid = vec![vec![true, false]];
+        let image = Image::from_grid(&grid, 2, |&b| if b { [1, 1, 1] } else { [2, 2, 2] });
+
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.pixels, [[1, 1, 1], [1, 1, 1], [2, 2, 2], [2, 2, 2]].repeat(2));
+    }
+}
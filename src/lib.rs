@@ -1,11 +1,23 @@
+pub mod automaton;
+pub mod cycle;
 pub mod elf;
+pub mod image;
+pub mod parallel;
+pub mod rng;
 
 #[macro_export]
 macro_rules! build_main {
-    ($input:literal, $( $part:literal => $solver:expr),+) => {
-        use std::time::Instant;
+    ($input:literal, $( $part:literal => $solver:expr),+ $(; $( $flag:literal => $handler:expr),+ )?) => {
         fn main() {
+            use std::time::Instant;
             let input: &str = include_str!(concat!("../input/", $input));
+            $($(
+            if std::env::args().nth(1).as_deref() == Some($flag) {
+                let args = std::env::args().skip(2).collect::<Vec<_>>();
+                $handler(input, &args);
+                return;
+            }
+            )+)?
             $(
             let start = Instant::now();
             let result = $solver(input);
@@ -19,8 +31,8 @@ macro_rules! build_main {
 #[macro_export]
 macro_rules! build_main_res {
     ($input:literal, $( $part:literal => $solver:expr),+) => {
-        use std::time::Instant;
         fn main() {
+            use std::time::Instant;
             let input: &str = include_str!(concat!("../input/", $input));
             $(
             let start = Instant::now();
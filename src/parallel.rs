@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Apply `f` to every item across all available threads.  Items are handed out in order, one at
+/// a time, and the results come back in the same order as `items`.
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(items.len());
+    let next = AtomicUsize::new(0);
+
+    let mut results = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| scope.spawn(|| {
+                let mut results = Vec::new();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else { return results };
+                    results.push((i, f(item)));
+                }
+            }))
+            .collect::<Vec<_>>();
+
+        workers.into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    results.sort_unstable_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_map() {
+        let items = (0..1000).collect::<Vec<u64>>();
+        assert_eq!(parallel_map(&items, |&x| x * x), items.iter().map(|&x| x * x).collect::<Vec<_>>());
+        assert_eq!(parallel_map(&[] as &[u64], |&x| x), []);
+    }
+}